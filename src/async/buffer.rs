@@ -0,0 +1,581 @@
+//! Holds a buffer that sits between the chasing thread and the receiving end of
+//! async modes, applying an overflow policy when the receiving end falls behind.
+
+use data::*;
+use control::ControlHandle;
+use errors::ChaseError;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{self, BufReader, SeekFrom};
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, UNIX_EPOCH};
+use std::ffi::OsStr;
+
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+
+#[cfg(feature = "with-serde")]
+use json::JsonLine;
+#[cfg(feature = "with-serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "with-serde")]
+use serde_json;
+#[cfg(feature = "with-serde")]
+use super::JsonItem;
+
+/// Things that can be written to and read back from a spill file
+pub(crate) trait Spill: Sized {
+    fn spill<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn unspill<R: BufRead>(reader: &mut R) -> io::Result<Self>;
+
+    /// How many lines this holds, for counting dropped and spilled lines
+    fn lines(&self) -> usize {
+        1
+    }
+}
+
+impl Spill for (String, Line, Pos) {
+    fn spill<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let (ref text, Line(line), Pos(pos)) = *self;
+        writeln!(writer, "{} {} {}", line, pos, text.len())?;
+        writer.write_all(text.as_bytes())
+    }
+
+    fn unspill<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let mut fields = header.trim_end().split(' ').map(|f| f.parse::<u64>());
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(Ok(line)), Some(Ok(pos)), Some(Ok(len))) => {
+                let text = read_string(reader, len as usize)?;
+                Ok((text, Line(line as usize), Pos(pos)))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt spill file entry header: {:?}", header),
+            )),
+        }
+    }
+}
+
+fn read_string<R: BufRead>(reader: &mut R, len: usize) -> io::Result<String> {
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl Spill for Record {
+    fn spill<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let read_at = self.read_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let timestamp = self.timestamp
+            .map(|t| t.duration_since(UNIX_EPOCH).unwrap_or_default());
+        let path = self.path.as_os_str().as_bytes();
+        writeln!(
+            writer,
+            "{} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            self.line.0,
+            self.pos.0,
+            self.file_id.0,
+            self.len,
+            self.generation,
+            self.seq,
+            read_at.as_secs(),
+            read_at.subsec_nanos(),
+            self.partial as u8,
+            timestamp.is_some() as u8,
+            timestamp.map(|t| t.as_secs()).unwrap_or(0),
+            timestamp.map(|t| t.subsec_nanos()).unwrap_or(0),
+            self.unmatched as u8,
+            self.fields.len(),
+            path.len(),
+            self.text.len()
+        )?;
+        writer.write_all(path)?;
+        writer.write_all(self.text.as_bytes())?;
+        for (key, value) in &self.fields {
+            writeln!(writer, "{} {}", key.len(), value.len())?;
+            writer.write_all(key.as_bytes())?;
+            writer.write_all(value.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn unspill<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let fields = header
+            .trim_end()
+            .split(' ')
+            .map(|f| f.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>();
+        match fields {
+            Ok(ref fields) if fields.len() == 16 => {
+                let mut path = vec![0; fields[14] as usize];
+                reader.read_exact(&mut path)?;
+                let text = read_string(reader, fields[15] as usize)?;
+                let mut grok_fields = BTreeMap::new();
+                for _ in 0..fields[13] {
+                    let mut lengths = String::new();
+                    reader.read_line(&mut lengths)?;
+                    let lengths = lengths
+                        .trim_end()
+                        .split(' ')
+                        .map(|l| l.parse::<usize>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    if lengths.len() != 2 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Corrupt spill file record field",
+                        ));
+                    }
+                    let key = read_string(reader, lengths[0])?;
+                    let value = read_string(reader, lengths[1])?;
+                    grok_fields.insert(key, value);
+                }
+                Ok(Record {
+                    text,
+                    line: Line(fields[0] as usize),
+                    pos: Pos(fields[1]),
+                    path: PathBuf::from(OsStr::from_bytes(&path)),
+                    file_id: FileId(fields[2]),
+                    len: fields[3],
+                    generation: fields[4] as usize,
+                    seq: fields[5],
+                    read_at: UNIX_EPOCH + Duration::new(fields[6], fields[7] as u32),
+                    partial: fields[8] != 0,
+                    timestamp: if fields[9] != 0 {
+                        Some(UNIX_EPOCH + Duration::new(fields[10], fields[11] as u32))
+                    } else {
+                        None
+                    },
+                    fields: grok_fields,
+                    unmatched: fields[12] != 0,
+                })
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt spill file record header: {:?}", header),
+            )),
+        }
+    }
+}
+
+// Only the line as it was read is spilled, and it gets parsed again when read back. Lines
+// that couldn't be parsed the first time can't be the second time either, so they come back
+// as they were delivered, raw.
+#[cfg(feature = "with-serde")]
+impl<T: DeserializeOwned> Spill for JsonItem<T> {
+    fn spill<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let (_, Line(line), Pos(pos)) = self.data;
+        writeln!(writer, "{} {} {}", line, pos, self.text.len())?;
+        writer.write_all(self.text.as_bytes())
+    }
+
+    fn unspill<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let (text, line, pos) = <(String, Line, Pos)>::unspill(reader)?;
+        let json = match serde_json::from_str(&text) {
+            Ok(value) => JsonLine::Parsed(value),
+            Err(_) => JsonLine::Raw(text.clone()),
+        };
+        Ok(JsonItem {
+            data: (json, line, pos),
+            text,
+        })
+    }
+}
+
+impl<T: Spill> Spill for Vec<T> {
+    fn spill<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", self.len())?;
+        for item in self {
+            item.spill(writer)?;
+        }
+        Ok(())
+    }
+
+    fn unspill<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let len = header.trim_end().parse::<usize>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt spill file batch header: {:?}", header),
+            )
+        })?;
+        (0..len).map(|_| T::unspill(reader)).collect()
+    }
+
+    fn lines(&self) -> usize {
+        self.iter().map(Spill::lines).sum()
+    }
+}
+
+/// Creates a buffer, giving back the end that the chasing thread pushes into and the end
+/// that a forwarding thread pops from.
+///
+/// Dropped and spilled lines are counted on the given ControlHandle.
+pub(crate) fn buffer<T>(
+    capacity: usize,
+    policy: OverflowPolicy,
+    counters: ControlHandle,
+) -> (BufferSender<T>, BufferReceiver<T>)
+where
+    T: Spill,
+{
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(Inner {
+            queue: VecDeque::new(),
+            spill_file: None,
+            closed_with: None,
+            receiver_gone: false,
+            sender_gone: false,
+        }),
+        cvar: Condvar::new(),
+        capacity: capacity.max(1),
+        policy,
+        counters,
+    });
+    (
+        BufferSender {
+            shared: shared.clone(),
+        },
+        BufferReceiver { shared },
+    )
+}
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    cvar: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    counters: ControlHandle,
+}
+
+struct Inner<T> {
+    queue: VecDeque<T>,
+    spill_file: Option<SpillFile>,
+    closed_with: Option<ChaseError>,
+    receiver_gone: bool,
+    sender_gone: bool,
+}
+
+struct SpillFile {
+    path: PathBuf,
+    writer: File,
+    reader: BufReader<File>,
+    pending: usize,
+}
+
+impl SpillFile {
+    fn create(path: &PathBuf) -> io::Result<SpillFile> {
+        let writer = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(path)?;
+        let reader = BufReader::new(File::open(path)?);
+        Ok(SpillFile {
+            path: path.clone(),
+            writer,
+            reader,
+            pending: 0,
+        })
+    }
+
+    fn push<T: Spill>(&mut self, item: &T) -> io::Result<()> {
+        item.spill(&mut self.writer)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    fn pop<T: Spill>(&mut self) -> io::Result<T> {
+        let item = T::unspill(&mut self.reader)?;
+        self.pending -= 1;
+        if self.pending == 0 {
+            // Everything has been read back, so start over with an empty file
+            self.writer.set_len(0)?;
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.reader.seek(SeekFrom::Start(0))?;
+        }
+        Ok(item)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+fn lock<'a, T>(shared: &'a Shared<T>) -> MutexGuard<'a, Inner<T>> {
+    shared.inner.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The chasing thread's end of the buffer
+pub(crate) struct BufferSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Spill> BufferSender<T> {
+    /// Pushes an item into the buffer, applying the overflow policy if it is full.
+    ///
+    /// Fails if the receiving end has gone away.
+    pub(crate) fn push(&self, item: T) -> Result<(), ChaseError> {
+        let shared = &*self.shared;
+        let mut inner = lock(shared);
+        if shared.policy == OverflowPolicy::Block {
+            while inner.queue.len() >= shared.capacity && !inner.receiver_gone {
+                inner = shared.cvar.wait(inner).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        if inner.receiver_gone {
+            return Err(inner
+                .closed_with
+                .take()
+                .unwrap_or(ChaseError::ReceiverDropped));
+        }
+        let spilling = inner
+            .spill_file
+            .as_ref()
+            .map(|spill_file| spill_file.pending > 0)
+            .unwrap_or(false);
+        if inner.queue.len() < shared.capacity && !spilling {
+            inner.queue.push_back(item);
+        } else {
+            match shared.policy {
+                // Block has already waited for room, so never actually gets here
+                OverflowPolicy::Block | OverflowPolicy::DropNewest => {
+                    shared.counters.count_dropped_lines(item.lines());
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(oldest) = inner.queue.pop_front() {
+                        shared.counters.count_dropped_lines(oldest.lines());
+                    }
+                    inner.queue.push_back(item);
+                }
+                OverflowPolicy::SpillToDisk(ref path) => {
+                    if inner.spill_file.is_none() {
+                        inner.spill_file = Some(SpillFile::create(path)?);
+                    }
+                    if let Some(ref mut spill_file) = inner.spill_file {
+                        spill_file.push(&item)?;
+                    }
+                    shared.counters.count_spilled_lines(item.lines());
+                }
+            }
+        }
+        shared.cvar.notify_all();
+        Ok(())
+    }
+}
+
+impl<T> Drop for BufferSender<T> {
+    fn drop(&mut self) {
+        lock(&self.shared).sender_gone = true;
+        self.shared.cvar.notify_all();
+    }
+}
+
+/// The forwarding thread's end of the buffer
+pub(crate) struct BufferReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Spill> BufferReceiver<T> {
+    /// Blocks until there is an item, giving back None once the sending end has gone
+    /// away and everything buffered has been handed out.
+    pub(crate) fn pop(&self) -> Option<T> {
+        let shared = &*self.shared;
+        let mut inner = lock(shared);
+        loop {
+            if let Some(item) = inner.queue.pop_front() {
+                // There's room now, in case the chasing thread is blocked on a full buffer
+                shared.cvar.notify_all();
+                return Some(item);
+            }
+            let spilled = match inner.spill_file {
+                Some(ref mut spill_file) if spill_file.pending > 0 => Some(spill_file.pop()),
+                _ => None,
+            };
+            match spilled {
+                Some(Ok(item)) => return Some(item),
+                Some(Err(e)) => {
+                    // Nothing sensible can be read back, so let the chasing thread know
+                    inner.receiver_gone = true;
+                    inner.closed_with = Some(e.into());
+                    return None;
+                }
+                None if inner.sender_gone => return None,
+                None => inner = shared.cvar.wait(inner).unwrap_or_else(|e| e.into_inner()),
+            }
+        }
+    }
+
+    /// Marks the buffer as closed, so that the chasing thread fails with the given error
+    /// on its next push.
+    pub(crate) fn close(&self, error: ChaseError) {
+        let mut inner = lock(&self.shared);
+        inner.receiver_gone = true;
+        inner.closed_with = Some(error);
+        self.shared.cvar.notify_all();
+    }
+}
+
+impl<T> Drop for BufferReceiver<T> {
+    fn drop(&mut self) {
+        lock(&self.shared).receiver_gone = true;
+        self.shared.cvar.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::*;
+
+    fn item(n: usize) -> (String, Line, Pos) {
+        (format!("line {}", n), Line(n), Pos(n as u64 * 10))
+    }
+
+    #[test]
+    fn drop_newest_test() {
+        let counters = ControlHandle::new();
+        let (tx, rx) = buffer(2, OverflowPolicy::DropNewest, counters.clone());
+        for n in 0..4 {
+            tx.push(item(n)).unwrap();
+        }
+        drop(tx);
+        assert_eq!(rx.pop(), Some(item(0)));
+        assert_eq!(rx.pop(), Some(item(1)));
+        assert_eq!(rx.pop(), None);
+        assert_eq!(counters.dropped_lines(), 2);
+    }
+
+    #[test]
+    fn drop_oldest_test() {
+        let counters = ControlHandle::new();
+        let (tx, rx) = buffer(2, OverflowPolicy::DropOldest, counters.clone());
+        for n in 0..4 {
+            tx.push(item(n)).unwrap();
+        }
+        drop(tx);
+        assert_eq!(rx.pop(), Some(item(2)));
+        assert_eq!(rx.pop(), Some(item(3)));
+        assert_eq!(rx.pop(), None);
+        assert_eq!(counters.dropped_lines(), 2);
+    }
+
+    #[test]
+    fn spill_to_disk_test() {
+        let temp_dir = TempDir::new("chase-test-buffer-spill").unwrap();
+        let spill_path = temp_dir.path().join("spill");
+        let counters = ControlHandle::new();
+        let (tx, rx) = buffer(
+            2,
+            OverflowPolicy::SpillToDisk(spill_path.clone()),
+            counters.clone(),
+        );
+        for n in 0..5 {
+            tx.push(item(n)).unwrap();
+        }
+        assert_eq!(counters.spilled_lines(), 3);
+        for n in 0..3 {
+            assert_eq!(rx.pop(), Some(item(n)));
+        }
+        // Still spilling, to keep things in order
+        tx.push(item(5)).unwrap();
+        for n in 3..6 {
+            assert_eq!(rx.pop(), Some(item(n)));
+        }
+        // Spill file has been drained, so we're back to buffering in memory
+        tx.push(item(6)).unwrap();
+        assert_eq!(counters.spilled_lines(), 4);
+        drop(tx);
+        assert_eq!(rx.pop(), Some(item(6)));
+        assert_eq!(rx.pop(), None);
+        assert_eq!(counters.dropped_lines(), 0);
+        drop(rx);
+        assert!(!spill_path.exists());
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn closed_test() {
+        let (tx, rx) = buffer(2, OverflowPolicy::DropNewest, ControlHandle::new());
+        rx.close(ChaseError::Custom("gone".into()));
+        match tx.push(item(0)) {
+            Err(ChaseError::Custom(e)) => assert_eq!(e.to_string(), "gone"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn spill_batches_test() {
+        let temp_dir = TempDir::new("chase-test-buffer-spill-batches").unwrap();
+        let counters = ControlHandle::new();
+        let (tx, rx) = buffer(
+            1,
+            OverflowPolicy::SpillToDisk(temp_dir.path().join("spill")),
+            counters.clone(),
+        );
+        tx.push(vec![item(0), item(1)]).unwrap();
+        tx.push(vec![item(2), item(3), item(4)]).unwrap();
+        tx.push(vec![]).unwrap();
+        assert_eq!(counters.spilled_lines(), 3);
+        drop(tx);
+        assert_eq!(rx.pop(), Some(vec![item(0), item(1)]));
+        assert_eq!(rx.pop(), Some(vec![item(2), item(3), item(4)]));
+        assert_eq!(rx.pop(), Some(vec![]));
+        assert_eq!(rx.pop(), None);
+        drop(rx);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn spill_records_test() {
+        let temp_dir = TempDir::new("chase-test-buffer-spill-records").unwrap();
+        let record = |n: usize| Record {
+            text: format!("line {}", n),
+            line: Line(n),
+            pos: Pos(n as u64 * 10),
+            path: temp_dir.path().join("test.log"),
+            file_id: FileId(42),
+            len: 10,
+            generation: 1,
+            seq: n as u64 + 100,
+            read_at: UNIX_EPOCH + Duration::new(1_500_000_000, 123),
+            partial: n == 2,
+            timestamp: if n == 1 {
+                None
+            } else {
+                Some(UNIX_EPOCH + Duration::new(1_400_000_000 + n as u64, 456))
+            },
+            fields: (0..n)
+                .map(|i| (format!("field {}", i), format!("value\n{}", i)))
+                .collect(),
+            unmatched: n == 0,
+        };
+        let (tx, rx) = buffer(
+            1,
+            OverflowPolicy::SpillToDisk(temp_dir.path().join("spill")),
+            ControlHandle::new(),
+        );
+        for n in 0..3 {
+            tx.push(record(n)).unwrap();
+        }
+        drop(tx);
+        for n in 0..3 {
+            assert_eq!(rx.pop(), Some(record(n)));
+        }
+        assert_eq!(rx.pop(), None);
+        drop(rx);
+        temp_dir.close().unwrap();
+    }
+}
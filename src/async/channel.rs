@@ -4,16 +4,30 @@
 use data::*;
 use control::*;
 
-use super::{thread_namer, SendData};
+use super::{channel_capacity, send_batches, spawn_sending, ChaseThread, SendData, SendEach,
+            SendRecords, Sending};
+#[cfg(feature = "with-serde")]
+use super::SendJson;
+#[cfg(feature = "with-serde")]
+use json::{JsonData, UnparseablePolicy};
+#[cfg(feature = "with-serde")]
+use serde::de::DeserializeOwned;
+use super::buffer::Spill;
+use sync::Deliver;
 
 use std::sync::mpsc::*;
-use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
 
 use errors::ChaseError;
 
 impl Chaser {
     /// Consumes the given chaser and gives you back a standard lib Channel to read
-    /// from
+    /// from, along with a ControlHandle that can be used to control the watch loop,
+    /// and to end it even when no new lines are arriving.
+    ///
+    /// How many lines can be read ahead of the receiving end, and what happens when
+    /// that many are waiting, is determined by the chaser's `channel_capacity` and
+    /// `overflow_policy`.
     ///
     /// ```
     /// # extern crate chase;
@@ -34,22 +48,25 @@ impl Chaser {
     ///   .open(&file_path)
     ///   .unwrap();
     ///
-    /// write!(file_write, "Hello, world 1\n").unwrap();
-    /// write!(file_write, "Hello, world 2\n").unwrap();
+    /// writeln!(file_write, "Hello, world 1").unwrap();
+    /// writeln!(file_write, "Hello, world 2").unwrap();
     ///
     /// let mut seen = String::new();
     ///
-    /// let (receiver, _) = chaser.run_channel().unwrap();
+    /// let (receiver, stop_handle, join_handle) = chaser.run_channel().unwrap();
     ///
     /// seen.push_str(&receiver.recv().unwrap().0);
     /// seen.push_str(&receiver.recv().unwrap().0);
     ///
     /// assert_eq!(seen.as_str(), "Hello, world 1Hello, world 2");
     ///
-    /// write!(file_write, "Hello, world 3\n").unwrap();
+    /// writeln!(file_write, "Hello, world 3").unwrap();
     /// seen.push_str(&receiver.recv().unwrap().0);
     /// assert_eq!(seen.as_str(), "Hello, world 1Hello, world 2Hello, world 3");
     ///
+    /// stop_handle.stop();
+    /// assert!(join_handle.join().unwrap().is_ok());
+    ///
     /// drop(receiver);
     /// drop(file_write);
     /// temp_dir.close().unwrap();
@@ -57,27 +74,121 @@ impl Chaser {
     /// ```
     pub fn run_channel(
         self,
-    ) -> Result<(Receiver<SendData>, JoinHandle<Result<(), ChaseError>>), ChaseError> {
-        let (tx, rx) = sync_channel(0);
-        let join_handle = Builder::new()
-            .name(thread_namer(&self.path))
-            .spawn(move || {
-                let mut moved_chaser = self;
-                moved_chaser.run(|line, num, pos| {
-                    tx.send((line.to_string(), num, pos))?;
-                    Ok(Control::Continue)
-                })?;
-                Ok(())
-            })?;
-        Ok((rx, join_handle))
+    ) -> Result<(Receiver<SendData>, ControlHandle, ChaseThread), ChaseError> {
+        run_channel_with(self, SendEach)
+    }
+
+    /// Consumes the given chaser and gives you back a standard lib Channel to read
+    /// batches of lines from, along with a ControlHandle that can be used to end the
+    /// watch loop.
+    ///
+    /// A batch is sent once it holds `max_lines` lines, or once its first line has been
+    /// waiting for `linger`. Lingering batches are checked for whenever the end of the
+    /// file is reached, so in practice, they can wait for up to `linger` plus the
+    /// chaser's `not_rotated_wait`.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # use std::time::Duration;
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-channel-batched-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// for i in 0..3 {
+    ///     writeln!(file_write, "Hello, world {}", i).unwrap();
+    /// }
+    ///
+    /// let (receiver, stop_handle, _) = chaser
+    ///     .run_channel_batched(2, Duration::from_millis(10))
+    ///     .unwrap();
+    ///
+    /// let first_batch = receiver.recv().unwrap();
+    /// assert_eq!(first_batch.len(), 2);
+    /// assert_eq!(first_batch[1], ("Hello, world 1".to_string(), Line(1), Pos(15)));
+    /// // The last line doesn't fill up a batch, so it goes out after lingering
+    /// assert_eq!(receiver.recv().unwrap().len(), 1);
+    ///
+    /// stop_handle.stop();
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn run_channel_batched(
+        self,
+        max_lines: usize,
+        linger: Duration,
+    ) -> Result<(Receiver<Vec<SendData>>, ControlHandle, ChaseThread), ChaseError> {
+        run_channel_with(self, move |send| send_batches(max_lines, linger, send))
+    }
+
+    /// Like `run_channel`, but sends a Record of every line read, which also says which
+    /// file it came from, when it was read, and so on.
+    pub fn run_channel_records(
+        self,
+    ) -> Result<(Receiver<Record>, ControlHandle, ChaseThread), ChaseError> {
+        run_channel_with(self, SendRecords)
+    }
+
+    /// Like `run_channel`, but parses every line read as JSON, either into a
+    /// `serde_json::Value` or into a type of your own, sending it along with its Line and
+    /// Pos. Lines that can't be parsed are dealt with according to `on_unparseable`.
+    #[cfg(feature = "with-serde")]
+    pub fn run_channel_json<T>(
+        self,
+        on_unparseable: UnparseablePolicy,
+    ) -> Result<(Receiver<JsonData<T>>, ControlHandle, ChaseThread), ChaseError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        run_channel_with(self, move |send| SendJson {
+            on_unparseable,
+            send,
+        })
     }
 }
 
+fn run_channel_with<T, B, D, M>(
+    chaser: Chaser,
+    deliverer: M,
+) -> Result<(Receiver<T>, ControlHandle, ChaseThread), ChaseError>
+where
+    T: Send + 'static,
+    B: Spill + Into<T> + Send + 'static,
+    D: Deliver + Send + 'static,
+    M: FnOnce(Sending<B>) -> D,
+    ChaseError: From<SendError<T>>,
+{
+    let handle = ControlHandle::new();
+    let (tx, rx) = sync_channel(channel_capacity(&chaser));
+    let join_handle = spawn_sending(
+        chaser,
+        &handle,
+        Box::new(move |item| Ok(tx.send(item)?)),
+        deliverer,
+    )?;
+    Ok((rx, handle, join_handle))
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::super::data::*;
+    use errors::ChaseError;
     use tempdir::*;
     use std::io::Write;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
 
     use std::fs::{rename, OpenOptions};
 
@@ -88,25 +199,24 @@ mod tests {
         let chaser = Chaser::new(&file_path);
 
         let mut file_write = OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open(&file_path)
             .unwrap();
 
-        write!(file_write, "Hello, world 1\n").unwrap();
-        write!(file_write, "Hello, world 2\n").unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
 
         let mut seen = String::new();
 
-        let (receiver, _) = chaser.run_channel().unwrap();
+        let (receiver, _, _) = chaser.run_channel().unwrap();
 
         seen.push_str(&receiver.recv().unwrap().0);
         seen.push_str(&receiver.recv().unwrap().0);
 
         assert_eq!(seen.as_str(), "Hello, world 1Hello, world 2");
 
-        write!(file_write, "Hello, world 3\n").unwrap();
+        writeln!(file_write, "Hello, world 3").unwrap();
         seen.push_str(&receiver.recv().unwrap().0);
         assert_eq!(seen.as_str(), "Hello, world 1Hello, world 2Hello, world 3");
 
@@ -114,13 +224,12 @@ mod tests {
         let mut file_write_new = {
             rename(&file_path, temp_dir.path().join("test.log.bk")).unwrap();
             OpenOptions::new()
-                .write(true)
                 .append(true)
                 .create(true)
                 .open(&file_path)
                 .unwrap()
         };
-        write!(file_write_new, "Hello, world 4\n").unwrap();
+        writeln!(file_write_new, "Hello, world 4").unwrap();
 
         seen.push_str(&receiver.recv().unwrap().0);
         assert_eq!(
@@ -132,4 +241,290 @@ mod tests {
         drop(file_write);
         temp_dir.close().unwrap();
     }
+
+    #[test]
+    fn stop_idle_channel_test() {
+        let temp_dir = TempDir::new("chase-test-channel-stop").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        // Waits long enough that the test would hang if stopping didn't interrupt them
+        chaser.not_rotated_wait = Duration::from_secs(60);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+
+        let (receiver, stop_handle, join_handle) = chaser.run_channel().unwrap();
+        assert_eq!(receiver.recv().unwrap().0, "Hello, world 1");
+
+        let stopped_at = Instant::now();
+        stop_handle.stop();
+        assert!(join_handle.join().unwrap().is_ok());
+        assert!(stopped_at.elapsed() < Duration::from_secs(60));
+        // The sending end is gone with the thread
+        assert!(receiver.recv().is_err());
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn stop_waiting_for_file_channel_test() {
+        let temp_dir = TempDir::new("chase-test-channel-stop-no-file").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.initial_no_file_wait = Duration::from_secs(60);
+
+        let (_receiver, stop_handle, join_handle) = chaser.run_channel().unwrap();
+        stop_handle.stop();
+        assert!(join_handle.join().unwrap().is_ok());
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn final_state_channel_test() {
+        let temp_dir = TempDir::new("chase-test-channel-state").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        let (receiver, _, join_handle) = chaser.run_channel().unwrap();
+        assert_eq!(receiver.recv().unwrap().0, "Hello, world 1");
+        assert_eq!(receiver.recv().unwrap().0, "Hello, world 2");
+        drop(receiver);
+        // Line 3 can't be sent, so the thread fails while trying to deliver it
+        writeln!(file_write, "Hello, world 3").unwrap();
+
+        let failure = join_handle.join().unwrap().unwrap_err();
+        match failure.error {
+            ChaseError::ReceiverDropped => (),
+            ref other => panic!("Unexpected error {:?}", other),
+        }
+        assert_eq!(failure.state.line, Line(2));
+        assert_eq!(failure.state.pos, Pos(30));
+        assert_eq!(failure.state.lines_read, 2);
+        assert_eq!(failure.state.rotations, 0);
+        assert_eq!(failure.state.seq, 2);
+        assert!(failure.state.file_id.is_some());
+
+        let mut resumed_chaser = Chaser::new(&file_path);
+        resumed_chaser.resume_from = Some(failure.state);
+        let (receiver, stop_handle, join_handle) = resumed_chaser.run_channel().unwrap();
+        assert_eq!(
+            receiver.recv().unwrap(),
+            ("Hello, world 3".to_string(), Line(2), Pos(30))
+        );
+        stop_handle.stop();
+        let state = join_handle.join().unwrap().unwrap();
+        assert_eq!(state.line, Line(3));
+        assert_eq!(state.pos, Pos(45));
+        assert_eq!(state.lines_read, 3);
+        assert_eq!(state.seq, 3);
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn overflow_channel_test() {
+        let temp_dir = TempDir::new("chase-test-channel-overflow").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.channel_capacity = 1;
+        chaser.overflow_policy = OverflowPolicy::DropNewest;
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        for n in 0..5 {
+            writeln!(file_write, "Hello, world {}", n).unwrap();
+        }
+
+        let (receiver, stop_handle, _) = chaser.run_channel().unwrap();
+        // Give the chasing thread time to read everything without us receiving anything
+        sleep(Duration::from_millis(250));
+
+        let mut received = Vec::new();
+        while let Ok((line, num, _)) = receiver.recv_timeout(Duration::from_millis(250)) {
+            received.push((line, num));
+        }
+        assert!(stop_handle.dropped_lines() > 0);
+        assert_eq!(received.len() + stop_handle.dropped_lines(), 5);
+        // Whatever made it through is still in order
+        assert_eq!(received[0], ("Hello, world 0".to_string(), Line(0)));
+        let mut sorted = received.clone();
+        sorted.sort_by_key(|&(_, num)| num);
+        assert_eq!(received, sorted);
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn spill_channel_test() {
+        let temp_dir = TempDir::new("chase-test-channel-spill").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.channel_capacity = 1;
+        chaser.overflow_policy = OverflowPolicy::SpillToDisk(temp_dir.path().join("spill"));
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        for n in 0..5 {
+            writeln!(file_write, "Hello, world {}", n).unwrap();
+        }
+
+        let (receiver, stop_handle, _) = chaser.run_channel().unwrap();
+        sleep(Duration::from_millis(250));
+
+        for n in 0..5 {
+            assert_eq!(receiver.recv().unwrap().1, Line(n));
+        }
+        assert!(stop_handle.spilled_lines() > 0);
+        assert_eq!(stop_handle.dropped_lines(), 0);
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn control_handle_channel_test() {
+        let temp_dir = TempDir::new("chase-test-channel-control").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.not_rotated_wait = Duration::from_secs(60);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        let (receiver, handle, join_handle) = chaser.run_channel().unwrap();
+        assert_eq!(receiver.recv().unwrap().1, Line(0));
+        assert_eq!(receiver.recv().unwrap().1, Line(1));
+
+        // The chaser is idle at this point, so rewinding has to wake it up
+        handle.rewind();
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            ("Hello, world 1".to_string(), Line(0), Pos(0))
+        );
+        assert_eq!(receiver.recv().unwrap().1, Line(1));
+
+        handle.stop();
+        assert!(join_handle.join().unwrap().is_ok());
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn run_channel_records_test() {
+        let temp_dir = TempDir::new("chase-test-channel-records").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.overflow_policy = OverflowPolicy::SpillToDisk(temp_dir.path().join("spill"));
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+
+        let (receiver, stop_handle, join_handle) = chaser.run_channel_records().unwrap();
+        let first = receiver.recv().unwrap();
+        assert_eq!(first.text, "Hello, world 1");
+        assert_eq!((first.line, first.pos, first.len), (Line(0), Pos(0), 15));
+        assert_eq!(first.path, file_path);
+        assert_eq!((first.generation, first.seq), (0, 0));
+        assert!(!first.partial);
+
+        rename(&file_path, temp_dir.path().join("test.log.bk")).unwrap();
+        let mut file_write_new = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write_new, "Hello, world 2").unwrap();
+
+        let second = receiver.recv().unwrap();
+        assert_eq!(second.text, "Hello, world 2");
+        assert_eq!((second.line, second.pos), (Line(0), Pos(0)));
+        assert_eq!((second.generation, second.seq), (1, 1));
+        assert_ne!(second.file_id, first.file_id);
+        assert!(second.read_at >= first.read_at);
+
+        stop_handle.stop();
+        let state = join_handle.join().unwrap().unwrap();
+        assert_eq!(state.file_id, Some(second.file_id));
+        assert_eq!((state.rotations, state.seq), (1, 2));
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[cfg(feature = "with-serde")]
+    #[test]
+    fn run_channel_json_test() {
+        use json::*;
+        use serde_json::Value;
+
+        let temp_dir = TempDir::new("chase-test-channel-json").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.overflow_policy = OverflowPolicy::SpillToDisk(temp_dir.path().join("spill"));
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, r#"{{"msg": "Hello, world 1"}}"#).unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        writeln!(file_write, "[1, 2, 3]").unwrap();
+
+        let (receiver, stop_handle, _) = chaser
+            .run_channel_json::<Value>(UnparseablePolicy::DeliverRaw)
+            .unwrap();
+        // Give the chaser a chance to spill a few lines
+        sleep(Duration::from_millis(100));
+        let received: Vec<(JsonLine<Value>, Line, Pos)> = receiver.iter().take(3).collect();
+        stop_handle.stop();
+
+        match received[0] {
+            (JsonLine::Parsed(ref value), Line(0), Pos(0)) => {
+                assert_eq!(value["msg"], "Hello, world 1")
+            }
+            ref other => panic!("Unexpected line {:?}", other),
+        }
+        assert_eq!(
+            received[1],
+            (JsonLine::Raw("Hello, world 2".to_string()), Line(1), Pos(26))
+        );
+        assert_eq!(
+            received[2],
+            (JsonLine::Parsed(json!([1, 2, 3])), Line(2), Pos(41))
+        );
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
 }
@@ -1,16 +1,190 @@
 #[cfg(feature = "stream")]
 mod stream;
 mod channel;
+mod buffer;
 
 use super::data::*;
+use super::control::*;
+use super::errors::{ChaseError, ChaseFailure};
+use super::sync::Deliver;
 
-use std::path::PathBuf;
+use super::batch::Batcher;
+#[cfg(feature = "with-serde")]
+use super::json::{parse_json, JsonData, UnparseablePolicy};
+#[cfg(feature = "with-serde")]
+use serde::de::DeserializeOwned;
+use self::buffer::{buffer, BufferReceiver, Spill};
+
+use std::io;
+use std::path::Path;
+use std::thread::{Builder, JoinHandle};
+use std::time::Duration;
 
 pub(crate) type SendData = (String, Line, Pos);
 
-pub(crate) fn thread_namer(path: &PathBuf) -> String {
+/// Handle to the thread doing the chasing in async modes. Joining it gives you the
+/// state the chaser stopped in, whether it stopped cleanly or because of an error.
+pub type ChaseThread = JoinHandle<Result<ChaseState, ChaseFailure>>;
+
+pub(crate) fn thread_namer(path: &Path) -> String {
     format!(
         "chase-thread-{}",
         path.to_str().unwrap_or("undisplayable-path")
     )
 }
+
+// Sends an item to the receiving end
+type Sending<T> = Box<dyn FnMut(T) -> Result<(), ChaseError> + Send>;
+
+// Hands every line read to the wrapped function, one at a time
+struct SendEach<F>(F);
+
+impl<F> Deliver for SendEach<F>
+where
+    F: FnMut(SendData) -> Result<(), ChaseError>,
+{
+    fn line(&mut self, running: &Chasing, _: usize) -> Result<Control, ChaseError> {
+        (self.0)((running.text().to_string(), running.line, running.pos))?;
+        Ok(Control::Continue)
+    }
+}
+
+// Hands a Record of every line read to the wrapped function, one at a time
+struct SendRecords<F>(F);
+
+impl<F> Deliver for SendRecords<F>
+where
+    F: FnMut(Record) -> Result<(), ChaseError>,
+{
+    fn line(&mut self, running: &Chasing, bytes_read: usize) -> Result<Control, ChaseError> {
+        (self.0)(running.record(bytes_read))?;
+        Ok(Control::Continue)
+    }
+}
+
+// A line parsed as JSON, along with the line as it was read, which is what gets spilled
+// to disk so that T doesn't need to be serializable
+#[cfg(feature = "with-serde")]
+pub(crate) struct JsonItem<T> {
+    pub(crate) data: JsonData<T>,
+    pub(crate) text: String,
+}
+
+#[cfg(feature = "with-serde")]
+impl<T> From<JsonItem<T>> for JsonData<T> {
+    fn from(item: JsonItem<T>) -> JsonData<T> {
+        item.data
+    }
+}
+
+// Hands every line read, parsed as JSON, to the wrapped function, one at a time
+#[cfg(feature = "with-serde")]
+struct SendJson<T> {
+    on_unparseable: UnparseablePolicy,
+    send: Sending<JsonItem<T>>,
+}
+
+#[cfg(feature = "with-serde")]
+impl<T: DeserializeOwned> Deliver for SendJson<T> {
+    fn line(&mut self, running: &Chasing, _: usize) -> Result<Control, ChaseError> {
+        if let Some(json) = parse_json(running, self.on_unparseable)? {
+            (self.send)(JsonItem {
+                data: (json, running.line, running.pos),
+                text: running.text().to_string(),
+            })?;
+        }
+        Ok(Control::Continue)
+    }
+}
+
+// Hands lines to the given function in batches
+fn send_batches(
+    max_lines: usize,
+    linger: Duration,
+    mut send: Sending<Vec<SendData>>,
+) -> impl Deliver + Send {
+    Batcher::new(max_lines, linger, move |batch| {
+        send(batch)?;
+        Ok(Control::Continue)
+    })
+}
+
+// How big the channel to the receiving end should be; if the overflow policy calls
+// for a buffer, that's where things wait instead.
+fn channel_capacity(chaser: &Chaser) -> usize {
+    if chaser.overflow_policy == OverflowPolicy::Block {
+        chaser.channel_capacity
+    } else {
+        0
+    }
+}
+
+// Spawns the chasing thread, using the given function to send items either directly or,
+// if the overflow policy calls for it, through a buffer and a forwarding thread. What the
+// chasing thread hands over (B) is turned into what gets sent (T) on the way out.
+fn spawn_sending<T, B, D, M>(
+    chaser: Chaser,
+    handle: &ControlHandle,
+    mut send: Sending<T>,
+    deliverer: M,
+) -> io::Result<ChaseThread>
+where
+    T: Send + 'static,
+    B: Spill + Into<T> + Send + 'static,
+    D: Deliver + Send + 'static,
+    M: FnOnce(Sending<B>) -> D,
+{
+    if chaser.overflow_policy == OverflowPolicy::Block {
+        spawn_chaser(
+            chaser,
+            handle,
+            deliverer(Box::new(move |item: B| send(item.into()))),
+        )
+    } else {
+        let (buffer_tx, buffer_rx) = buffer(
+            chaser.channel_capacity,
+            chaser.overflow_policy.clone(),
+            handle.clone(),
+        );
+        spawn_forwarder(&chaser.path, buffer_rx, move |item: B| send(item.into()))?;
+        spawn_chaser(
+            chaser,
+            handle,
+            deliverer(Box::new(move |item| buffer_tx.push(item))),
+        )
+    }
+}
+
+// Spawns the thread that does the chasing
+fn spawn_chaser<D>(chaser: Chaser, handle: &ControlHandle, deliver: D) -> io::Result<ChaseThread>
+where
+    D: Deliver + Send + 'static,
+{
+    let thread_handle = handle.clone();
+    Builder::new()
+        .name(thread_namer(&chaser.path))
+        .spawn(move || {
+            let mut moved_chaser = chaser;
+            moved_chaser.run_controlled(&thread_handle, deliver)
+        })
+}
+
+// Spawns a thread that hands everything popped from the buffer to the given function,
+// closing the buffer as soon as that fails
+fn spawn_forwarder<T, F>(path: &Path, buffer: BufferReceiver<T>, mut forward: F) -> io::Result<()>
+where
+    T: Spill + Send + 'static,
+    F: FnMut(T) -> Result<(), ChaseError> + Send + 'static,
+{
+    Builder::new()
+        .name(format!("{}-forwarder", thread_namer(path)))
+        .spawn(move || {
+            while let Some(item) = buffer.pop() {
+                if let Err(e) = forward(item) {
+                    buffer.close(e);
+                    break;
+                }
+            }
+        })?;
+    Ok(())
+}
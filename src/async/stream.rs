@@ -4,9 +4,19 @@
 use data::*;
 use control::*;
 
-use super::{thread_namer, SendData};
+use super::{channel_capacity, send_batches, spawn_sending, ChaseThread, SendData, SendEach,
+            SendRecords, Sending};
+#[cfg(feature = "with-serde")]
+use super::SendJson;
+#[cfg(feature = "with-serde")]
+use json::{JsonData, UnparseablePolicy};
+#[cfg(feature = "with-serde")]
+use serde::de::DeserializeOwned;
+use super::buffer::Spill;
+use sync::Deliver;
+
+use std::time::Duration;
 
-use std::thread::{Builder, JoinHandle};
 use futures::{Future, Sink};
 use futures::sync::mpsc::*;
 
@@ -14,7 +24,13 @@ use errors::ChaseError;
 
 impl Chaser {
     /// Consume the given Chaser and returns a Stream from which you can
-    /// read attempts to read lines from the file
+    /// read attempts to read lines from the file, along with a ControlHandle that
+    /// can be used to control the watch loop, and to end it even when no new lines
+    /// are arriving.
+    ///
+    /// How many lines can be read ahead of the Stream's consumer, and what happens when
+    /// that many are waiting, is determined by the chaser's `channel_capacity` and
+    /// `overflow_policy`.
     ///
     /// ```
     /// # extern crate chase;
@@ -38,10 +54,10 @@ impl Chaser {
     ///   .open(&file_path)
     ///   .unwrap();
     ///
-    /// write!(file_write, "Hello, world 1\n").unwrap();
-    /// write!(file_write, "Hello, world 2\n").unwrap();
+    /// writeln!(file_write, "Hello, world 1").unwrap();
+    /// writeln!(file_write, "Hello, world 2").unwrap();
     ///
-    /// let (stream, _) = chaser.run_stream().unwrap();
+    /// let (stream, _, _) = chaser.run_stream().unwrap();
     ///
     /// let accumulated = stream
     /// .take(3) // we'll add another one after this is declared to show things are really async
@@ -50,7 +66,7 @@ impl Chaser {
     /// future::ok(acc)
     /// });
     ///
-    /// write!(file_write, "Hello, world 3\n").unwrap();
+    /// writeln!(file_write, "Hello, world 3").unwrap();
     /// assert_eq!(
     ///     accumulated.wait(),
     ///     Ok("Hello, world 1Hello, world 2Hello, world 3".to_string())
@@ -61,22 +77,111 @@ impl Chaser {
     /// # }
     /// ```
     pub fn run_stream(
-        mut self,
-    ) -> Result<(Receiver<SendData>, JoinHandle<Result<(), ChaseError>>), ChaseError> {
-        let (mut tx, rx) = channel(0);
-
-        let join_handle = Builder::new()
-            .name(thread_namer(&self.path))
-            .spawn(move || {
-                self.run(|line, num, pos| {
-                    let next_tx = tx.clone().send((line.to_string(), num, pos)).wait()?;
-                    tx = next_tx;
-                    Ok(Control::Continue)
-                })?;
-                Ok(())
-            })?;
-        Ok((rx, join_handle))
+        self,
+    ) -> Result<(Receiver<SendData>, ControlHandle, ChaseThread), ChaseError> {
+        run_stream_with(self, SendEach)
     }
+
+    /// Consume the given Chaser and returns a Stream of batches of lines read from the
+    /// file, along with a ControlHandle that can be used to end the watch loop.
+    ///
+    /// Batches are put together the same way as with `run_channel_batched`.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # extern crate futures;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # use std::time::Duration;
+    /// # use futures::{Future, Stream};
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-stream-batched-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// for i in 0..4 {
+    ///     writeln!(file_write, "Hello, world {}", i).unwrap();
+    /// }
+    ///
+    /// let (stream, _, _) = chaser
+    ///     .run_stream_batched(2, Duration::from_secs(60))
+    ///     .unwrap();
+    ///
+    /// let batches = stream.take(2).collect().wait().unwrap();
+    /// let lines: Vec<Line> = batches.iter().flat_map(|b| b.iter().map(|l| l.1)).collect();
+    /// assert_eq!(lines, vec![Line(0), Line(1), Line(2), Line(3)]);
+    ///
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn run_stream_batched(
+        self,
+        max_lines: usize,
+        linger: Duration,
+    ) -> Result<(Receiver<Vec<SendData>>, ControlHandle, ChaseThread), ChaseError> {
+        run_stream_with(self, move |send| send_batches(max_lines, linger, send))
+    }
+
+    /// Like `run_stream`, but gives you a Stream of Records, which also say which file
+    /// each line came from, when it was read, and so on.
+    pub fn run_stream_records(
+        self,
+    ) -> Result<(Receiver<Record>, ControlHandle, ChaseThread), ChaseError> {
+        run_stream_with(self, SendRecords)
+    }
+
+    /// Like `run_stream`, but parses every line read as JSON, either into a
+    /// `serde_json::Value` or into a type of your own. Lines that can't be parsed are
+    /// dealt with according to `on_unparseable`.
+    #[cfg(feature = "with-serde")]
+    pub fn run_stream_json<T>(
+        self,
+        on_unparseable: UnparseablePolicy,
+    ) -> Result<(Receiver<JsonData<T>>, ControlHandle, ChaseThread), ChaseError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        run_stream_with(self, move |send| SendJson {
+            on_unparseable,
+            send,
+        })
+    }
+}
+
+fn run_stream_with<T, B, D, M>(
+    chaser: Chaser,
+    deliverer: M,
+) -> Result<(Receiver<T>, ControlHandle, ChaseThread), ChaseError>
+where
+    T: Send + 'static,
+    B: Spill + Into<T> + Send + 'static,
+    D: Deliver + Send + 'static,
+    M: FnOnce(Sending<B>) -> D,
+    ChaseError: From<SendError<T>>,
+{
+    let handle = ControlHandle::new();
+    let (mut tx, rx) = channel(channel_capacity(&chaser));
+    let join_handle = spawn_sending(
+        chaser,
+        &handle,
+        Box::new(move |item| {
+            let next_tx = tx.clone().send(item).wait()?;
+            tx = next_tx;
+            Ok(())
+        }),
+        deliverer,
+    )?;
+    Ok((rx, handle, join_handle))
 }
 
 #[cfg(test)]
@@ -98,17 +203,16 @@ mod tests {
         let chaser = Chaser::new(&file_path);
 
         let mut file_write = OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open(&file_path)
             .unwrap();
 
-        let (stream, _) = chaser.run_stream().unwrap();
+        let (stream, _, _) = chaser.run_stream().unwrap();
 
-        write!(file_write, "Hello, world 1\n").unwrap();
-        write!(file_write, "Hello, world 2\n").unwrap();
-        write!(file_write, "Hello, world 3\n").unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        writeln!(file_write, "Hello, world 3").unwrap();
 
         let accumulated = stream
             .take(4) // We'll add another entry and rotate afterwards
@@ -124,13 +228,12 @@ mod tests {
         let mut file_write_new = {
             rename(&file_path, temp_dir.path().join("test.log.bk")).unwrap();
             OpenOptions::new()
-                .write(true)
                 .append(true)
                 .create(true)
                 .open(&file_path)
                 .unwrap()
         };
-        write!(file_write_new, "Hello, world 4\n").unwrap();
+        writeln!(file_write_new, "Hello, world 4").unwrap();
 
         assert_eq!(
             accumulated.wait(),
@@ -140,4 +243,102 @@ mod tests {
         drop(file_write);
         temp_dir.close().unwrap();
     }
+
+    #[test]
+    fn stop_idle_stream_test() {
+        let temp_dir = TempDir::new("chase-test-stream-stop").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.not_rotated_wait = Duration::from_secs(60);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+
+        let (stream, stop_handle, join_handle) = chaser.run_stream().unwrap();
+        let (first, stream) = stream.into_future().wait().map_err(|_| ()).unwrap();
+        assert_eq!(first.unwrap().0, "Hello, world 1");
+
+        stop_handle.stop();
+        assert!(join_handle.join().unwrap().is_ok());
+        // The stream ends once the chasing thread is gone
+        assert_eq!(stream.collect().wait(), Ok(vec![]));
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn run_stream_records_test() {
+        let temp_dir = TempDir::new("chase-test-stream-records").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        write!(file_write, "Hello").unwrap();
+
+        let (stream, stop_handle, _) = chaser.run_stream_records().unwrap();
+        let records = stream.take(2).collect().wait().unwrap();
+        stop_handle.stop();
+
+        assert_eq!(records[0].text, "Hello, world 1");
+        assert!(!records[0].partial);
+        assert_eq!(records[1].text, "Hello");
+        assert_eq!((records[1].pos, records[1].len), (Pos(15), 5));
+        assert!(records[1].partial);
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[cfg(feature = "with-serde")]
+    #[test]
+    fn run_stream_json_test() {
+        use json::*;
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Entry {
+            msg: String,
+        }
+
+        let temp_dir = TempDir::new("chase-test-stream-json").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, r#"{{"msg": "Hello, world 2"}}"#).unwrap();
+
+        let (stream, stop_handle, _) = chaser
+            .run_stream_json::<Entry>(UnparseablePolicy::Skip)
+            .unwrap();
+        let (first, _) = stream.into_future().wait().map_err(|_| ()).unwrap();
+        assert_eq!(
+            first,
+            Some((
+                JsonLine::Parsed(Entry {
+                    msg: "Hello, world 2".to_string(),
+                }),
+                Line(1),
+                Pos(15),
+            ))
+        );
+        assert_eq!(stop_handle.skipped_lines(), 1);
+        stop_handle.stop();
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
 }
@@ -0,0 +1,305 @@
+//! Holds logic for gathering lines into batches before delivering them
+
+use data::*;
+use control::*;
+use errors::ChaseError;
+use sync::{call_back, Deliver};
+
+use std::mem;
+use std::time::{Duration, Instant};
+
+impl Chaser {
+    /// Start chasing a file synchronously, receiving lines in batches.
+    ///
+    /// The provided callback function will be invoked with a batch as soon as it holds
+    /// `max_lines` lines, or once its first line has been waiting for `linger`. Lingering
+    /// batches are checked for whenever the end of the file is reached, so in practice,
+    /// they can wait for up to `linger` plus `not_rotated_wait`.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # use std::time::Duration;
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-sync-batched-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let mut chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// for i in 0..5 {
+    ///     writeln!(file_write, "Hello, world {}", i).unwrap();
+    /// }
+    ///
+    /// let mut batch_sizes = Vec::new();
+    /// let mut last_line = None;
+    ///
+    /// chaser.run_batched(2, Duration::from_millis(10), |batch| {
+    ///     batch_sizes.push(batch.len());
+    ///     last_line = batch.last().map(|&(_, line, _)| line);
+    ///     if last_line < Some(Line(4)) {
+    ///         Ok(Control::Continue)
+    ///     } else {
+    ///         Ok(Control::Stop)
+    ///     }
+    /// }).unwrap();
+    ///
+    /// assert_eq!(batch_sizes, vec![2, 2, 1]);
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn run_batched<F>(
+        &mut self,
+        max_lines: usize,
+        linger: Duration,
+        mut f: F,
+    ) -> Result<(), ChaseError>
+    where
+        F: FnMut(&[(String, Line, Pos)]) -> Result<Control, ChaseError>,
+    {
+        let policy = self.on_callback_error;
+        let handle = ControlHandle::new();
+        let batcher = {
+            let handle = handle.clone();
+            Batcher::new(max_lines, linger, move |batch| {
+                call_back(policy, &handle, batch[0].1, batch[0].2, || f(&batch))
+            })
+        };
+        self.run_controlled(&handle, batcher)
+            .map(|_| ())
+            .map_err(|failure| failure.error)
+    }
+}
+
+/// Gathers lines into batches, handing them off once a batch is full or has been
+/// lingering for long enough.
+pub(crate) struct Batcher<F> {
+    max_lines: usize,
+    linger: Duration,
+    batch: Vec<(String, Line, Pos)>,
+    started: Option<Instant>,
+    /// The state from before the first line of the batch
+    held_from: Option<ChaseState>,
+    flush: F,
+}
+
+impl<F> Batcher<F>
+where
+    F: FnMut(Vec<(String, Line, Pos)>) -> Result<Control, ChaseError>,
+{
+    pub(crate) fn new(max_lines: usize, linger: Duration, flush: F) -> Batcher<F> {
+        let max_lines = max_lines.max(1);
+        Batcher {
+            max_lines,
+            linger,
+            batch: Vec::with_capacity(max_lines),
+            started: None,
+            held_from: None,
+            flush,
+        }
+    }
+
+    // Adds a line to the current batch, flushing it if it's full or has lingered
+    fn push(&mut self, text: String, num: Line, pos: Pos) -> Result<Control, ChaseError> {
+        if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+        self.batch.push((text, num, pos));
+        if self.batch.len() >= self.max_lines || self.lingered() {
+            self.flush()
+        } else {
+            Ok(Control::Continue)
+        }
+    }
+
+    fn lingered(&self) -> bool {
+        self.started
+            .map(|started| started.elapsed() >= self.linger)
+            .unwrap_or(false)
+    }
+
+    fn flush(&mut self) -> Result<Control, ChaseError> {
+        if self.batch.is_empty() {
+            Ok(Control::Continue)
+        } else {
+            self.started = None;
+            self.held_from = None;
+            let batch = mem::replace(&mut self.batch, Vec::with_capacity(self.max_lines));
+            (self.flush)(batch)
+        }
+    }
+}
+
+impl<F> Deliver for Batcher<F>
+where
+    F: FnMut(Vec<(String, Line, Pos)>) -> Result<Control, ChaseError>,
+{
+    fn line(&mut self, running: &Chasing, _: usize) -> Result<Control, ChaseError> {
+        if self.batch.is_empty() {
+            self.held_from = Some(running.state());
+        }
+        self.push(running.text().to_string(), running.line, running.pos)
+    }
+
+    fn idle(&mut self) -> Result<Control, ChaseError> {
+        if self.lingered() {
+            self.flush()
+        } else {
+            Ok(Control::Continue)
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), ChaseError> {
+        self.flush().map(|_| ())
+    }
+
+    fn held_from(&self) -> Option<ChaseState> {
+        self.held_from.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use batch::*;
+    use tempdir::*;
+    use std::fs::{remove_file, OpenOptions};
+    use std::io::Write;
+    use std::thread::{self, sleep};
+
+    #[test]
+    fn batcher_size_test() {
+        let mut flushed = Vec::new();
+        {
+            let mut batcher = Batcher::new(2, Duration::from_secs(60), |batch| {
+                flushed.push(batch);
+                Ok(Control::Continue)
+            });
+            for n in 0..5 {
+                batcher.push(n.to_string(), Line(n), Pos(n as u64)).unwrap();
+            }
+            batcher.idle().unwrap();
+            assert_eq!(batcher.batch.len(), 1);
+            batcher.finish().unwrap();
+        }
+        let sizes: Vec<usize> = flushed.iter().map(|batch| batch.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(flushed[2][0], ("4".to_string(), Line(4), Pos(4)));
+    }
+
+    #[test]
+    fn batcher_linger_test() {
+        let mut flushed = Vec::new();
+        {
+            let mut batcher = Batcher::new(100, Duration::from_millis(20), |batch| {
+                flushed.push(batch);
+                Ok(Control::Continue)
+            });
+            batcher.push("a".to_string(), Line(0), Pos(0)).unwrap();
+            batcher.idle().unwrap();
+            sleep(Duration::from_millis(30));
+            batcher.idle().unwrap();
+        }
+        assert_eq!(flushed, vec![vec![("a".to_string(), Line(0), Pos(0))]]);
+    }
+
+    #[test]
+    fn batcher_checkpoint_test() {
+        let temp_dir = TempDir::new("chase-test-batch-checkpoint").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        let handle = ControlHandle::new();
+        let checking = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                sleep(Duration::from_millis(100));
+                let checkpoint = handle.checkpoint().unwrap();
+                handle.stop();
+                (checkpoint.line, checkpoint.pos)
+            })
+        };
+        let mut flushed = Vec::new();
+        let state = chaser
+            .run_controlled(
+                &handle,
+                Batcher::new(10, Duration::from_secs(60), |batch| {
+                    flushed.push(batch.len());
+                    Ok(Control::Continue)
+                }),
+            )
+            .unwrap();
+
+        // While the batch is waiting, the checkpoint stays before it
+        assert_eq!(checking.join().unwrap(), (Line(0), Pos(0)));
+        assert_eq!(flushed, vec![2]);
+        assert_eq!(handle.checkpoint(), Some(state.clone()));
+        assert_eq!((state.line, state.pos), (Line(2), Pos(30)));
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn batcher_failure_test() {
+        let temp_dir = TempDir::new("chase-test-batch-failure").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.rotation_check_attempts = Some(1);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        drop(file_write);
+
+        let removing = {
+            let file_path = file_path.clone();
+            thread::spawn(move || {
+                sleep(Duration::from_millis(100));
+                remove_file(&file_path).unwrap();
+            })
+        };
+        let mut flushed = Vec::new();
+        let failure = chaser
+            .run_controlled(
+                &ControlHandle::new(),
+                Batcher::new(10, Duration::from_secs(60), |batch| {
+                    flushed.push(batch);
+                    Ok(Control::Continue)
+                }),
+            )
+            .unwrap_err();
+        removing.join().unwrap();
+
+        // The batch never went out, so resuming goes back to before it
+        match failure.error {
+            ChaseError::RotationCheckExhausted { .. } => (),
+            ref other => panic!("Unexpected error {:?}", other),
+        }
+        assert!(flushed.is_empty());
+        assert_eq!(
+            (failure.state.line, failure.state.pos, failure.state.seq),
+            (Line(0), Pos(0), 0)
+        );
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use serde_json;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use chase::*;
+
+// How often the state file is saved to while chasing
+const STATE_SAVE_INTERVAL_MILLIS: u64 = 1000;
+
+// Keeps a state file up to date with how far each file has been chased, so that chasing
+// can pick up from there next time
+pub(crate) struct Checkpointer {
+    path: PathBuf,
+    files: Vec<String>,
+    saved: Mutex<Saved>,
+    // The version of the states last written to the state file
+    written: Mutex<u64>,
+}
+
+struct Saved {
+    // Every state in the file, by path, including those of files we aren't chasing
+    states: BTreeMap<String, ChaseState>,
+    // Which of our files are done with, and so have their final state saved already
+    done: Vec<bool>,
+    // Bumped whenever the states change, so that an older snapshot of them is never
+    // written over a newer one
+    version: u64,
+}
+
+impl Checkpointer {
+    pub(crate) fn load(path: PathBuf, files: &[String]) -> Result<Checkpointer, Box<dyn Error>> {
+        let states = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))
+                .map_err(|e| format!("Could not read state from {}: {}", path.display(), e))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Checkpointer {
+            path,
+            files: files.to_vec(),
+            saved: Mutex::new(Saved {
+                states,
+                done: vec![false; files.len()],
+                version: 0,
+            }),
+            written: Mutex::new(0),
+        })
+    }
+
+    pub(crate) fn state_of(&self, index: usize) -> Option<ChaseState> {
+        self.lock().states.get(&self.files[index]).cloned()
+    }
+
+    // Saves where each file still being chased has got to every so often, until they're
+    // all done with
+    pub(crate) fn keep_saving(&self, handles: &[ControlHandle]) {
+        loop {
+            thread::sleep(Duration::from_millis(STATE_SAVE_INTERVAL_MILLIS));
+            let (version, states) = {
+                let mut saved = self.lock();
+                if saved.done.iter().all(|&done| done) {
+                    return;
+                }
+                for (index, handle) in handles.iter().enumerate() {
+                    if let (false, Some(checkpoint)) = (saved.done[index], handle.checkpoint()) {
+                        saved.states.insert(self.files[index].clone(), checkpoint);
+                    }
+                }
+                saved.version += 1;
+                (saved.version, saved.states.clone())
+            };
+            if let Err(e) = self.save(version, &states) {
+                eprintln!("Could not save state to {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    // Saves the state a file was left in once it's done with
+    pub(crate) fn finish(&self, index: usize, state: ChaseState) -> Result<(), Box<dyn Error>> {
+        let (version, states) = {
+            let mut saved = self.lock();
+            saved.done[index] = true;
+            saved.states.insert(self.files[index].clone(), state);
+            saved.version += 1;
+            (saved.version, saved.states.clone())
+        };
+        self.save(version, &states)
+    }
+
+    // Writes a snapshot of the states to a temporary file first, so that the state file is
+    // never left half-written, one snapshot at a time and unless a newer one got there first
+    fn save(
+        &self,
+        version: u64,
+        states: &BTreeMap<String, ChaseState>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut written = self.written.lock().expect("Checkpointer lock poisoned");
+        if *written > version {
+            return Ok(());
+        }
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        serde_json::to_writer_pretty(File::create(&temp_path)?, states)?;
+        fs::rename(&temp_path, &self.path)?;
+        *written = version;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Saved> {
+        self.saved.lock().expect("Checkpointer lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cli::checkpoint::*;
+    use tempdir::*;
+
+    fn state(line: usize) -> ChaseState {
+        ChaseState {
+            file_id: Some(FileId(1)),
+            line: Line(line),
+            pos: Pos(line as u64 * 10),
+            lines_read: line,
+            rotations: 0,
+            seq: line as u64,
+        }
+    }
+
+    #[test]
+    fn checkpointer_test() {
+        let temp_dir = TempDir::new("chase-test-checkpointer").unwrap();
+        let path = temp_dir.path().join("state.json");
+        let files = vec!["a.log".to_string(), "b.log".to_string()];
+
+        let checkpointer = Checkpointer::load(path.clone(), &files).unwrap();
+        assert_eq!(checkpointer.state_of(0), None);
+        checkpointer.finish(0, state(2)).unwrap();
+        checkpointer.finish(1, state(3)).unwrap();
+
+        // A snapshot taken before the last one was written doesn't get written over it
+        let mut older = BTreeMap::new();
+        older.insert("a.log".to_string(), state(1));
+        checkpointer.save(1, &older).unwrap();
+
+        let reloaded = Checkpointer::load(path.clone(), &files).unwrap();
+        assert_eq!(reloaded.state_of(0), Some(state(2)));
+        assert_eq!(reloaded.state_of(1), Some(state(3)));
+
+        // States of files we aren't chasing are kept as they are
+        let reloaded = Checkpointer::load(path.clone(), &files[1..]).unwrap();
+        reloaded.finish(0, state(4)).unwrap();
+        let reloaded = Checkpointer::load(path, &files).unwrap();
+        assert_eq!(reloaded.state_of(0), Some(state(2)));
+        assert_eq!(reloaded.state_of(1), Some(state(4)));
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn checkpointer_unreadable_test() {
+        let temp_dir = TempDir::new("chase-test-checkpointer-unreadable").unwrap();
+        let path = temp_dir.path().join("state.json");
+        fs::write(&path, "not json").unwrap();
+        let error = Checkpointer::load(path, &["a.log".to_string()]).err().unwrap();
+        assert!(error.to_string().starts_with("Could not read state from"));
+        temp_dir.close().unwrap();
+    }
+}
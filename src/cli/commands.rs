@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chase::*;
+
+use Options;
+
+// How long after starting the --pipe command it may be started again, so that one that
+// keeps failing isn't started over and over. Lines that come along in between are dropped.
+const PIPE_RESTART_DELAY_MILLIS: u64 = 1000;
+
+// Hands lines that are printed over to the commands given with --exec and --pipe
+pub(crate) struct Commands {
+    // Where lines go to have the --exec command run with them, on a thread of its own so
+    // that waiting on --exec-limit doesn't hold up printing. They queue up for as long as
+    // it takes; --debounce is the way to keep floods of them down.
+    exec: Option<mpsc::Sender<(String, Line, String)>>,
+    exec_thread: Option<thread::JoinHandle<()>>,
+    pipe: Option<String>,
+    piped_to: Option<Child>,
+    pipe_started: Option<Instant>,
+    debounce: Option<Duration>,
+    last_handed_over: Option<Instant>,
+}
+
+impl Commands {
+    pub(crate) fn new(options: &Options) -> Commands {
+        let (exec, exec_thread) = match options.exec {
+            Some(ref exec) => {
+                let (sender, receiver) = mpsc::channel();
+                let (exec, limit) = (exec.clone(), options.exec_limit);
+                let thread = thread::spawn(move || run_exec(&exec, limit, receiver));
+                (Some(sender), Some(thread))
+            }
+            None => (None, None),
+        };
+        Commands {
+            exec,
+            exec_thread,
+            pipe: options.pipe.clone(),
+            piped_to: None,
+            pipe_started: None,
+            debounce: options.debounce,
+            last_handed_over: None,
+        }
+    }
+
+    pub(crate) fn hand_over(&mut self, text: &str, line: Line, path: &str) -> Result<(), Box<dyn Error>> {
+        if self.exec.is_none() && self.pipe.is_none() {
+            return Ok(());
+        }
+        if let (Some(debounce), Some(last)) = (self.debounce, self.last_handed_over) {
+            if last.elapsed() < debounce {
+                return Ok(());
+            }
+        }
+        self.last_handed_over = Some(Instant::now());
+        if let Some(ref sender) = self.exec {
+            sender
+                .send((text.to_string(), line, path.to_string()))
+                .map_err(|_| "Stopped running --exec")?;
+        }
+        if self.pipe.is_some() {
+            // If the command died on us, it gets another go at the line once restarted.
+            // Should that fail too, or it's too soon to restart it, the line is dropped,
+            // and a later one starts it again.
+            match self.write_to_pipe(text) {
+                Err(ref e) if self.may_restart_pipe() => {
+                    eprintln!("{}", e);
+                    if let Err(e) = self.write_to_pipe(text) {
+                        eprintln!("{}, dropping the line", e);
+                    }
+                }
+                Err(e) => eprintln!("{}, dropping the line", e),
+                Ok(()) => (),
+            }
+        }
+        Ok(())
+    }
+
+    // Writes a line to the --pipe command, starting it if it isn't running. If writing
+    // fails, or we can't tell whether it's running, the command is killed, so that it's
+    // started afresh next time.
+    fn write_to_pipe(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let pipe = self.pipe.as_ref().expect("Only called with --pipe");
+        let running = match self.piped_to {
+            Some(ref mut child) => match child.try_wait() {
+                Ok(status) => status.is_none(),
+                Err(e) => {
+                    eprintln!("Could not check on {:?}: {}", pipe, e);
+                    false
+                }
+            },
+            None => false,
+        };
+        if !running {
+            if let Some(mut child) = self.piped_to.take() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            if !self.may_restart_pipe() {
+                let delay = Duration::from_millis(PIPE_RESTART_DELAY_MILLIS);
+                let started = format!("was started less than {:?} ago", delay);
+                return Err(format!("{:?} is not running, and {}", pipe, started).into());
+            }
+            if self.pipe_started.is_some() {
+                eprintln!("{:?} is not running, starting it again", pipe);
+            }
+            self.pipe_started = Some(Instant::now());
+            let child = shell(pipe, &[])
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Could not run {:?}: {}", pipe, e))?;
+            self.piped_to = Some(child);
+        }
+        let mut child = self.piped_to.take().expect("Started above");
+        let written = {
+            let stdin = child.stdin.as_mut().expect("Started with a piped stdin");
+            writeln!(stdin, "{}", text).and_then(|_| stdin.flush())
+        };
+        match written {
+            Ok(()) => {
+                self.piped_to = Some(child);
+                Ok(())
+            }
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(format!("Could not write to {:?}: {}", pipe, e).into())
+            }
+        }
+    }
+
+    fn may_restart_pipe(&self) -> bool {
+        let delay = Duration::from_millis(PIPE_RESTART_DELAY_MILLIS);
+        self.pipe_started
+            .map(|started| started.elapsed() >= delay)
+            .unwrap_or(true)
+    }
+
+    // Waits for the commands still running, closing the --pipe command's stdin so that it
+    // knows there is nothing more coming
+    pub(crate) fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        // The --exec thread runs out of lines once its sender is gone
+        self.exec = None;
+        if let Some(thread) = self.exec_thread.take() {
+            thread.join().map_err(|_| "Stopped running --exec")?;
+        }
+        if let Some(mut child) = self.piped_to.take() {
+            drop(child.stdin.take());
+            child.wait()?;
+        }
+        Ok(())
+    }
+}
+
+// Runs the --exec command with each line that comes along, until there are no more. Once
+// there are `limit` of them running, we wait for the oldest to finish, unless another one
+// already has.
+fn run_exec(exec: &str, limit: usize, lines: mpsc::Receiver<(String, Line, String)>) {
+    let mut running: VecDeque<Child> = VecDeque::new();
+    for (text, line, path) in lines {
+        let mut finished = Vec::new();
+        for (i, child) in running.iter_mut().enumerate() {
+            if child.try_wait().map(|status| status.is_some()).unwrap_or(true) {
+                finished.push(i);
+            }
+        }
+        for i in finished.into_iter().rev() {
+            running.remove(i);
+        }
+        while running.len() >= limit {
+            if let Some(mut oldest) = running.pop_front() {
+                let _ = oldest.wait();
+            }
+        }
+        let line_number = line.0.to_string();
+        let spawned = shell(exec, &[&text, &line_number, &path])
+            .env("CHASE_LINE", &text)
+            .env("CHASE_LINE_NUMBER", &line_number)
+            .env("CHASE_PATH", &path)
+            .stdin(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(child) => running.push_back(child),
+            Err(e) => eprintln!("Could not run {:?}: {}", exec, e),
+        }
+    }
+    for mut child in running {
+        let _ = child.wait();
+    }
+}
+
+// Runs a command with the system's shell. On Unix, the given arguments are $1, $2, etc.
+fn shell(command: &str, args: &[&str]) -> Command {
+    if cfg!(unix) {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command).arg("sh").args(args);
+        shell
+    } else {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    }
+}
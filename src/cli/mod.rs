@@ -0,0 +1,4 @@
+// The parts of the binary that aren't about parsing arguments or chasing the files
+pub mod checkpoint;
+pub mod commands;
+pub mod output;
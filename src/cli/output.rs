@@ -0,0 +1,174 @@
+use chase::*;
+
+// How lines are printed
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Output {
+    Text,
+    Json,
+    Template(Vec<Piece>),
+}
+
+// Part of a template: either text to print as is, or a field of the line
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Piece {
+    Literal(String),
+    Text,
+    Path,
+    Line,
+    Pos,
+    Generation,
+}
+
+pub(crate) fn parse_output(s: &str) -> Result<Output, String> {
+    match s {
+        "text" => Ok(Output::Text),
+        "json" => Ok(Output::Json),
+        _ if s.starts_with("template=") => parse_template(&s["template=".len()..]),
+        _ => Err(format!(
+            "{:?} is not an output we know of; try text, json or template=...",
+            s
+        )),
+    }
+}
+
+// Splits a template into pieces up front, so that lines can be printed without looking for
+// placeholders in them. {{ and }} stand for { and }.
+fn parse_template(template: &str) -> Result<Output, String> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+                literal.push(c);
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                let piece = match name.as_str() {
+                    _ if !closed => {
+                        return Err(format!("{:?} has a {{ that isn't closed", template))
+                    }
+                    "text" => Piece::Text,
+                    "path" => Piece::Path,
+                    "line" => Piece::Line,
+                    "pos" => Piece::Pos,
+                    "generation" => Piece::Generation,
+                    _ => {
+                        return Err(format!(
+                            "{{{}}} is not a field we know of; try text, path, line, pos or \
+                             generation",
+                            name
+                        ))
+                    }
+                };
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(literal.split_off(0)));
+                }
+                pieces.push(piece);
+            }
+            _ => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    Ok(Output::Template(pieces))
+}
+
+pub(crate) fn render(pieces: &[Piece], record: &Record) -> String {
+    let mut rendered = String::new();
+    for piece in pieces {
+        match *piece {
+            Piece::Literal(ref literal) => rendered.push_str(literal),
+            Piece::Text => rendered.push_str(&record.text),
+            Piece::Path => rendered.push_str(&record.path.display().to_string()),
+            Piece::Line => rendered.push_str(&record.line.0.to_string()),
+            Piece::Pos => rendered.push_str(&record.pos.0.to_string()),
+            Piece::Generation => rendered.push_str(&record.generation.to_string()),
+        }
+    }
+    rendered
+}
+
+// Puts every pair on its own line, with values lined up, and a blank line after them
+pub(crate) fn pretty(pairs: &Logfmt) -> String {
+    let width = pairs.pairs.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    let mut printed = String::new();
+    for (key, value) in &pairs.pairs {
+        match value {
+            Some(value) => printed.push_str(&format!("{:width$}  {}\n", key, value, width = width)),
+            None => printed.push_str(&format!("{}\n", key)),
+        }
+    }
+    printed
+}
+
+#[cfg(test)]
+mod tests {
+    use cli::output::*;
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    #[test]
+    fn parse_template_test() {
+        assert_eq!(
+            parse_template("{line}: {text} {{ok}}"),
+            Ok(Output::Template(vec![
+                Piece::Line,
+                Piece::Literal(": ".to_string()),
+                Piece::Text,
+                Piece::Literal(" {ok}".to_string()),
+            ]))
+        );
+        assert_eq!(
+            parse_output("template={path}{pos}{generation}"),
+            Ok(Output::Template(vec![Piece::Path, Piece::Pos, Piece::Generation]))
+        );
+        assert_eq!(parse_output("template="), Ok(Output::Template(vec![])));
+        assert!(parse_template("{text").unwrap_err().contains("isn't closed"));
+        assert!(parse_template("{level}").unwrap_err().contains("{level} is not a field"));
+        assert!(parse_output("yaml").is_err());
+    }
+
+    #[test]
+    fn render_test() {
+        let record = Record {
+            text: "Hello, world".to_string(),
+            line: Line(3),
+            pos: Pos(42),
+            path: PathBuf::from("/var/log/app.log"),
+            file_id: FileId(1),
+            len: 12,
+            generation: 2,
+            seq: 4,
+            read_at: SystemTime::now(),
+            partial: false,
+            timestamp: None,
+            fields: Default::default(),
+            unmatched: false,
+        };
+        let pieces = match parse_template("{path}:{line}@{pos}/{generation} {{{text}}}") {
+            Ok(Output::Template(pieces)) => pieces,
+            other => panic!("Not a template: {:?}", other),
+        };
+        assert_eq!(
+            render(&pieces, &record),
+            "/var/log/app.log:3@42/2 {Hello, world}"
+        );
+    }
+
+    #[test]
+    fn pretty_test() {
+        let pairs = Logfmt::parse("level=info msg=\"all good\" verbose");
+        assert_eq!(pretty(&pairs), "level    info\nmsg      all good\nverbose\n");
+    }
+}
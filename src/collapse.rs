@@ -0,0 +1,372 @@
+//! Holds logic for collapsing runs of repeated lines into one, syslog's "last message
+//! repeated N times" style.
+
+use data::*;
+use control::*;
+use errors::{ChaseError, ChaseFailure};
+use sync::{call_back, Deliver};
+
+use regex::Regex;
+
+use std::time::{Duration, Instant};
+
+const UUID_PATTERN: &str =
+    r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}";
+const NUMBER_PATTERN: &str = r"[0-9]+(?:\.[0-9]+)?";
+
+/// A run of repeated lines, collapsed into one
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct Repeated {
+    /// The first line of the run, without its trailing newline
+    pub text: String,
+    /// How many lines there were in the run
+    pub count: usize,
+    /// Line number of the first line of the run
+    pub first_line: Line,
+    /// Byte-position of the first line of the run
+    pub first_pos: Pos,
+    /// Line number of the last line of the run
+    pub last_line: Line,
+    /// Byte-position of the last line of the run
+    pub last_pos: Pos,
+}
+
+impl Chaser {
+    /// Start chasing a file synchronously, collapsing runs of consecutive lines that are
+    /// the same into one.
+    ///
+    /// With `masked`, lines that only differ in their numbers and UUIDs count as the same
+    /// too, and the first of them is the one delivered.
+    ///
+    /// The provided callback function will be invoked with a run once a different line
+    /// comes along, or once the run has been going for `flush_after`, in which case the
+    /// next repeat starts a new run. That is checked for whenever the end of the file is
+    /// reached, so in practice, runs can wait for up to `flush_after` plus
+    /// `not_rotated_wait`. A run still going when chasing stops is delivered then.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # use std::time::Duration;
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-sync-collapsed-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let mut chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// for i in 0..3 {
+    ///     writeln!(file_write, "Retrying request {}", i).unwrap();
+    /// }
+    /// writeln!(file_write, "Gave up").unwrap();
+    ///
+    /// let mut seen = Vec::new();
+    /// chaser.run_collapsed(true, Duration::from_secs(60), |run| {
+    ///     seen.push((run.text.clone(), run.count, run.first_line, run.last_line));
+    ///     Ok(Control::Stop)
+    /// }).unwrap();
+    ///
+    /// assert_eq!(seen, vec![("Retrying request 0".to_string(), 3, Line(0), Line(2))]);
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn run_collapsed<F>(
+        &mut self,
+        masked: bool,
+        flush_after: Duration,
+        f: F,
+    ) -> Result<(), ChaseError>
+    where
+        F: FnMut(&Repeated) -> Result<Control, ChaseError>,
+    {
+        self.run_collapsed_with_handle(&ControlHandle::new(), masked, flush_after, f)
+            .map(|_| ())
+            .map_err(|failure| failure.error)
+    }
+
+    /// Start chasing a file synchronously, like `run_collapsed`, but with a ControlHandle,
+    /// as with `run_with_handle`.
+    ///
+    /// Whether things went well or not, the state the chaser stopped in is handed back.
+    pub fn run_collapsed_with_handle<F>(
+        &mut self,
+        handle: &ControlHandle,
+        masked: bool,
+        flush_after: Duration,
+        mut f: F,
+    ) -> Result<ChaseState, ChaseFailure>
+    where
+        F: FnMut(&Repeated) -> Result<Control, ChaseError>,
+    {
+        let policy = self.on_callback_error;
+        let collapser = {
+            let handle = handle.clone();
+            Collapser::new(masked, flush_after, move |run| {
+                call_back(policy, &handle, run.first_line, run.first_pos, || f(&run))
+            })
+        };
+        self.run_controlled(handle, collapser)
+    }
+}
+
+/// Collapses runs of repeated lines, handing them off once they come to an end or have
+/// been going for long enough.
+pub(crate) struct Collapser<F> {
+    masks: Option<(Regex, Regex)>,
+    flush_after: Duration,
+    /// The run so far, what its lines look like once masked, and when it started
+    pending: Option<(Repeated, String, Instant)>,
+    /// The state from before the first line of the run, or of the line that would have
+    /// started one if we hadn't been told to stop
+    held_from: Option<ChaseState>,
+    flush: F,
+}
+
+impl<F> Collapser<F>
+where
+    F: FnMut(Repeated) -> Result<Control, ChaseError>,
+{
+    pub(crate) fn new(masked: bool, flush_after: Duration, flush: F) -> Collapser<F> {
+        let masks = if masked {
+            Some((
+                Regex::new(UUID_PATTERN).expect("The UUID pattern is valid"),
+                Regex::new(NUMBER_PATTERN).expect("The number pattern is valid"),
+            ))
+        } else {
+            None
+        };
+        Collapser {
+            masks,
+            flush_after,
+            pending: None,
+            held_from: None,
+            flush,
+        }
+    }
+
+    // Adds a line to the current run if it's a repeat, otherwise flushing the run and
+    // starting a new one, unless flushing the run told us to stop
+    pub(crate) fn push(&mut self, text: &str, num: Line, pos: Pos) -> Result<Control, ChaseError> {
+        let key = self.key(text);
+        let expired = self.expired();
+        if let Some((ref mut run, ref pending_key, _)) = self.pending {
+            if !expired && *pending_key == key {
+                run.count += 1;
+                run.last_line = num;
+                run.last_pos = pos;
+                return Ok(Control::Continue);
+            }
+        }
+        let run = Repeated {
+            text: text.to_string(),
+            count: 1,
+            first_line: num,
+            first_pos: pos,
+            last_line: num,
+            last_pos: pos,
+        };
+        let control = match self.pending.take() {
+            Some((previous, _, _)) => (self.flush)(previous)?,
+            None => Control::Continue,
+        };
+        if control != Control::Stop {
+            self.pending = Some((run, key, Instant::now()));
+        }
+        Ok(control)
+    }
+
+    fn key(&self, text: &str) -> String {
+        match self.masks {
+            Some((ref uuids, ref numbers)) => {
+                let without_uuids = uuids.replace_all(text, "<uuid>");
+                numbers.replace_all(&without_uuids, "<n>").into_owned()
+            }
+            None => text.to_string(),
+        }
+    }
+
+    fn expired(&self) -> bool {
+        self.pending
+            .as_ref()
+            .map(|&(_, _, started)| started.elapsed() >= self.flush_after)
+            .unwrap_or(false)
+    }
+
+    fn flush(&mut self) -> Result<Control, ChaseError> {
+        match self.pending.take() {
+            Some((run, _, _)) => {
+                self.held_from = None;
+                (self.flush)(run)
+            }
+            None => Ok(Control::Continue),
+        }
+    }
+}
+
+impl<F> Deliver for Collapser<F>
+where
+    F: FnMut(Repeated) -> Result<Control, ChaseError>,
+{
+    fn line(&mut self, running: &Chasing, _: usize) -> Result<Control, ChaseError> {
+        let control = self.push(running.text(), running.line, running.pos)?;
+        let started_run = match self.pending {
+            Some((ref run, _, _)) => run.count == 1,
+            None => false,
+        };
+        if started_run || control == Control::Stop {
+            self.held_from = Some(running.state());
+        }
+        Ok(control)
+    }
+
+    fn idle(&mut self) -> Result<Control, ChaseError> {
+        if self.expired() {
+            self.flush()
+        } else {
+            Ok(Control::Continue)
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), ChaseError> {
+        self.flush().map(|_| ())
+    }
+
+    fn held_from(&self) -> Option<ChaseState> {
+        self.held_from.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use collapse::*;
+    use tempdir::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::thread::sleep;
+
+    fn pushing<F>(collapser: &mut Collapser<F>, lines: &[&str])
+    where
+        F: FnMut(Repeated) -> Result<Control, ChaseError>,
+    {
+        for (n, text) in lines.iter().enumerate() {
+            collapser
+                .push(text, Line(n), Pos(n as u64 * 10))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn collapser_test() {
+        let mut flushed = Vec::new();
+        {
+            let mut collapser = Collapser::new(false, Duration::from_secs(60), |run| {
+                flushed.push(run);
+                Ok(Control::Continue)
+            });
+            pushing(&mut collapser, &["a", "a", "a", "b", "a 1", "a 2"]);
+            collapser.idle().unwrap();
+            collapser.finish().unwrap();
+        }
+        let runs: Vec<_> = flushed
+            .iter()
+            .map(|run| (run.text.as_str(), run.count, run.first_line, run.last_line))
+            .collect();
+        assert_eq!(
+            runs,
+            vec![
+                ("a", 3, Line(0), Line(2)),
+                ("b", 1, Line(3), Line(3)),
+                ("a 1", 1, Line(4), Line(4)),
+                ("a 2", 1, Line(5), Line(5)),
+            ]
+        );
+        assert_eq!((flushed[0].first_pos, flushed[0].last_pos), (Pos(0), Pos(20)));
+    }
+
+    #[test]
+    fn collapser_masked_test() {
+        let mut flushed = Vec::new();
+        {
+            let mut collapser = Collapser::new(true, Duration::from_secs(60), |run| {
+                flushed.push(run);
+                Ok(Control::Continue)
+            });
+            pushing(
+                &mut collapser,
+                &[
+                    "took 12ms for 5e0b0a4e-8c7c-4bd5-9d6b-3f0b2f5c9a11",
+                    "took 3.5ms for 0f6c3b1a-1d2e-4f3a-8b9c-0d1e2f3a4b5c",
+                    "took a while",
+                ],
+            );
+            collapser.finish().unwrap();
+        }
+        let runs: Vec<_> = flushed.iter().map(|run| run.count).collect();
+        assert_eq!(runs, vec![2, 1]);
+        assert_eq!(
+            flushed[0].text,
+            "took 12ms for 5e0b0a4e-8c7c-4bd5-9d6b-3f0b2f5c9a11"
+        );
+    }
+
+    #[test]
+    fn collapser_flush_after_test() {
+        let mut flushed = Vec::new();
+        {
+            let mut collapser = Collapser::new(false, Duration::from_millis(20), |run| {
+                flushed.push(run.count);
+                Ok(Control::Continue)
+            });
+            pushing(&mut collapser, &["a", "a"]);
+            collapser.idle().unwrap();
+            sleep(Duration::from_millis(30));
+            collapser.idle().unwrap();
+            collapser.push("a", Line(2), Pos(20)).unwrap();
+            sleep(Duration::from_millis(30));
+            collapser.push("a", Line(3), Pos(30)).unwrap();
+            collapser.finish().unwrap();
+        }
+        assert_eq!(flushed, vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn collapsed_stop_test() {
+        let temp_dir = TempDir::new("chase-test-collapse-stop").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "a").unwrap();
+        writeln!(file_write, "a").unwrap();
+        writeln!(file_write, "b").unwrap();
+
+        let mut flushed = Vec::new();
+        let handle = ControlHandle::new();
+        let state = chaser
+            .run_collapsed_with_handle(&handle, false, Duration::from_secs(60), |run| {
+                flushed.push(run.count);
+                Ok(Control::Stop)
+            })
+            .unwrap();
+
+        // The line that ended the run was never handed off, so it's to be read again
+        assert_eq!(flushed, vec![2]);
+        assert_eq!((state.line, state.pos, state.seq), (Line(2), Pos(4), 2));
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+}
@@ -1,10 +1,333 @@
 //! Holds control constructs for continuing or exiting the synchronous
 //! watch loop
 
-/// When chasing a file synchronously, use this to control when to exit the
-/// follow loop.
-#[derive(PartialEq, Eq, Debug)]
+use data::{ChaseState, Pos};
+use errors::ChaseError;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// When chasing a file synchronously, use this to control what the follow loop
+/// does after the current line.
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Control {
+    /// Exit the follow loop
     Stop,
+    /// Carry on with the next line
     Continue,
+    /// Carry on from the given byte-position in the current file. If it falls in the
+    /// middle of a line, the rest of that line is read next.
+    SeekTo(Pos),
+    /// Carry on from the top of the current file
+    Rewind,
+    /// Skip past everything that has been written to the current file so far
+    SkipToEnd,
+    /// Wait for the given duration before carrying on with the next line
+    PauseFor(Duration),
+}
+
+/// A handle for controlling a running chaser from another thread.
+///
+/// Stopping interrupts any wait the chaser is in (waiting for the file to appear,
+/// for a rotation check, or for new lines to be written), so the watch loop exits
+/// within one wait interval and finishes with `Ok`.
+///
+/// Other Controls (seeking, rewinding, etc.) are queued up and applied in order
+/// before the next line is read. In async modes, lines that were already sent or
+/// buffered will still be delivered.
+///
+/// In async modes with an overflow policy that doesn't block, the handle also keeps
+/// count of the lines that had to be dropped or spilled to disk. Lines skipped because of
+/// an ErrorPolicy are counted and reported on it too, as are lines filtered out by the
+/// chaser's Filter, sampled out by its Sampling or dropped by its RateLimit, lines it
+/// redacted something from, lines its TimestampFormats found no timestamp in and lines
+/// that didn't match its Grok.
+///
+/// The handle also keeps a checkpoint of how far the chaser has got, which can be
+/// persisted while it runs so that chasing can pick up from there later.
+///
+/// Handles are cheap to clone, and all clones refer to the same chaser.
+#[derive(Debug, Clone, Default)]
+pub struct ControlHandle {
+    inner: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<HandleState>,
+    cvar: Condvar,
+    dropped_lines: AtomicUsize,
+    spilled_lines: AtomicUsize,
+    skipped_lines: AtomicUsize,
+    filtered_lines: AtomicUsize,
+    untimestamped_lines: AtomicUsize,
+    unmatched_lines: AtomicUsize,
+    sampled_out_lines: AtomicUsize,
+    rate_limited_lines: AtomicUsize,
+    redacted_lines: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct HandleState {
+    stopped: bool,
+    pending: VecDeque<Control>,
+    skipped: VecDeque<ChaseError>,
+    checkpoint: Option<ChaseState>,
+}
+
+/// How many of the errors that caused lines to be skipped are held on to, until they are
+/// taken from the ControlHandle. Past this, the oldest ones are let go of.
+pub const MAX_REPORTED_SKIPS: usize = 100;
+
+impl ControlHandle {
+    /// Creates a new handle that has not been stopped
+    pub fn new() -> ControlHandle {
+        ControlHandle::default()
+    }
+
+    /// Signals the chaser to stop, waking it up if it is currently waiting
+    pub fn stop(&self) {
+        self.lock().stopped = true;
+        self.inner.cvar.notify_all();
+    }
+
+    /// Whether or not stop has been signalled
+    pub fn is_stopped(&self) -> bool {
+        self.lock().stopped
+    }
+
+    /// Queues up a Control to be applied by the chaser, waking it up if it is currently
+    /// waiting for new lines.
+    pub fn control(&self, control: Control) {
+        match control {
+            Control::Stop => self.stop(),
+            Control::Continue => (),
+            other => {
+                self.lock().pending.push_back(other);
+                self.inner.cvar.notify_all();
+            }
+        }
+    }
+
+    /// Moves the chaser to the given byte-position in the current file
+    pub fn seek_to(&self, pos: Pos) {
+        self.control(Control::SeekTo(pos))
+    }
+
+    /// Moves the chaser to the top of the current file
+    pub fn rewind(&self) {
+        self.control(Control::Rewind)
+    }
+
+    /// Moves the chaser past everything that has been written to the current file so far
+    pub fn skip_to_end(&self) {
+        self.control(Control::SkipToEnd)
+    }
+
+    /// Pauses the chaser for the given duration
+    pub fn pause_for(&self, duration: Duration) {
+        self.control(Control::PauseFor(duration))
+    }
+
+    /// How many lines have been dropped because the receiving end wasn't keeping up
+    pub fn dropped_lines(&self) -> usize {
+        self.inner.dropped_lines.load(Ordering::SeqCst)
+    }
+
+    /// How many lines have been spilled to disk because the receiving end wasn't keeping up
+    pub fn spilled_lines(&self) -> usize {
+        self.inner.spilled_lines.load(Ordering::SeqCst)
+    }
+
+    /// How many lines have been skipped because of an ErrorPolicy
+    pub fn skipped_lines(&self) -> usize {
+        self.inner.skipped_lines.load(Ordering::SeqCst)
+    }
+
+    /// Takes the errors that caused lines to be skipped since the last time this was
+    /// called, oldest first (up to `MAX_REPORTED_SKIPS` of them)
+    pub fn take_skipped(&self) -> Vec<ChaseError> {
+        self.lock().skipped.drain(..).collect()
+    }
+
+    /// The state the chaser was last checkpointed in, i.e. with every line before it
+    /// already delivered, so it can be set as a Chaser's `resume_from`. Checkpoints are
+    /// taken whenever the chaser catches up with the end of the file or stops, and every
+    /// so many lines in between. None until the file has been opened.
+    ///
+    /// Lines still waiting in a batch or a run of repeats haven't been delivered yet, so
+    /// the checkpoint stays before them until they are. In async modes, delivered means
+    /// sent or buffered, not necessarily received.
+    pub fn checkpoint(&self) -> Option<ChaseState> {
+        self.lock().checkpoint.clone()
+    }
+
+    pub(crate) fn set_checkpoint(&self, state: ChaseState) {
+        self.lock().checkpoint = Some(state);
+    }
+
+    /// How many lines have been filtered out by the chaser's Filter
+    pub fn filtered_lines(&self) -> usize {
+        self.inner.filtered_lines.load(Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "filter")]
+    pub(crate) fn count_filtered_lines(&self, lines: usize) {
+        self.inner.filtered_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    /// How many Records have been made from lines that none of the chaser's
+    /// TimestampFormats found a timestamp in
+    pub fn untimestamped_lines(&self) -> usize {
+        self.inner.untimestamped_lines.load(Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "timestamps")]
+    pub(crate) fn count_untimestamped_lines(&self, lines: usize) {
+        self.inner.untimestamped_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    /// How many Records have been made from lines that didn't match the chaser's Grok
+    pub fn unmatched_lines(&self) -> usize {
+        self.inner.unmatched_lines.load(Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "grok")]
+    pub(crate) fn count_unmatched_lines(&self, lines: usize) {
+        self.inner.unmatched_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    /// How many lines have been left out by the chaser's Sampling
+    pub fn sampled_out_lines(&self) -> usize {
+        self.inner.sampled_out_lines.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn count_sampled_out_lines(&self, lines: usize) {
+        self.inner.sampled_out_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    /// How many lines have been dropped for being over the chaser's RateLimit
+    pub fn rate_limited_lines(&self) -> usize {
+        self.inner.rate_limited_lines.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn count_rate_limited_lines(&self, lines: usize) {
+        self.inner.rate_limited_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    /// How many lines have had something redacted by the chaser's Redaction
+    pub fn redacted_lines(&self) -> usize {
+        self.inner.redacted_lines.load(Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "redact")]
+    pub(crate) fn count_redacted_lines(&self, lines: usize) {
+        self.inner.redacted_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    pub(crate) fn report_skipped(&self, error: ChaseError) {
+        self.inner.skipped_lines.fetch_add(1, Ordering::SeqCst);
+        let mut state = self.lock();
+        if state.skipped.len() >= MAX_REPORTED_SKIPS {
+            state.skipped.pop_front();
+        }
+        state.skipped.push_back(error);
+    }
+
+    pub(crate) fn count_dropped_lines(&self, lines: usize) {
+        self.inner.dropped_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    pub(crate) fn count_spilled_lines(&self, lines: usize) {
+        self.inner.spilled_lines.fetch_add(lines, Ordering::SeqCst);
+    }
+
+    /// Takes the next queued up Control, if any
+    pub(crate) fn next_pending(&self) -> Option<Control> {
+        self.lock().pending.pop_front()
+    }
+
+    /// Waits for the given duration, or until stop is signalled, whichever comes
+    /// first. Returns true if stop has been signalled.
+    pub(crate) fn wait(&self, duration: Duration) -> bool {
+        self.wait_while(duration, |_| true).stopped
+    }
+
+    /// Like `wait`, but also stops waiting as soon as a Control has been queued up.
+    pub(crate) fn wait_for_controls(&self, duration: Duration) -> bool {
+        self.wait_while(duration, |state| state.pending.is_empty())
+            .stopped
+    }
+
+    fn wait_while<F>(&self, duration: Duration, keep_waiting: F) -> MutexGuard<'_, HandleState>
+    where
+        F: Fn(&HandleState) -> bool,
+    {
+        let deadline = Instant::now() + duration;
+        let mut state = self.lock();
+        while !state.stopped && keep_waiting(&state) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self.inner
+                .cvar
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        state
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HandleState> {
+        self.inner.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use control::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn stop_handle_wait_test() {
+        let handle = ControlHandle::new();
+        assert!(!handle.wait(Duration::from_millis(10)));
+        assert!(!handle.is_stopped());
+
+        let other = handle.clone();
+        let started = Instant::now();
+        let waiter = thread::spawn(move || other.wait(Duration::from_secs(60)));
+        thread::sleep(Duration::from_millis(50));
+        handle.stop();
+        assert!(waiter.join().unwrap());
+        assert!(started.elapsed() < Duration::from_secs(60));
+        assert!(handle.is_stopped());
+    }
+
+    #[test]
+    fn control_handle_pending_test() {
+        let handle = ControlHandle::new();
+        handle.control(Control::Continue);
+        assert_eq!(handle.next_pending(), None);
+
+        let other = handle.clone();
+        let started = Instant::now();
+        let waiter = thread::spawn(move || other.wait_for_controls(Duration::from_secs(60)));
+        thread::sleep(Duration::from_millis(50));
+        handle.rewind();
+        handle.seek_to(Pos(3));
+        assert!(!waiter.join().unwrap());
+        assert!(started.elapsed() < Duration::from_secs(60));
+
+        assert_eq!(handle.next_pending(), Some(Control::Rewind));
+        assert_eq!(handle.next_pending(), Some(Control::SeekTo(Pos(3))));
+        assert_eq!(handle.next_pending(), None);
+        assert!(!handle.is_stopped());
+        handle.control(Control::Stop);
+        assert!(handle.is_stopped());
+    }
 }
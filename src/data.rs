@@ -1,11 +1,24 @@
 //! Holds various data structures used for following files
 
+use std::cell::Cell;
 use std::io::BufReader;
 use std::fs::File;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use control::ControlHandle;
+#[cfg(feature = "filter")]
+use filter::Matcher;
+#[cfg(feature = "grok")]
+use grok::GrokMatcher;
+use limit::{Limiter, Sampler};
+#[cfg(feature = "redact")]
+use redact::Redactor;
+#[cfg(feature = "timestamps")]
+use timestamp::{Extractor, TimestampFormat};
+
 pub const DEFAULT_ROTATION_CHECK_WAIT_MILLIS: u64 = 100;
 pub const DEFAULT_NOT_ROTATED_WAIT_MILLIS: u64 = 50;
 
@@ -17,9 +30,247 @@ pub struct Line(pub usize);
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
 pub struct Pos(pub u64);
 
+/// Identifies a file independently of its path (on unix, this is the inode number), which
+/// is how we tell that a file has been rotated.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
-pub(crate) struct FileId(pub(crate) u64);
+pub struct FileId(pub u64);
+
+/// Where a chaser was when it stopped, along with a few counters.
+///
+/// Line and Pos point at the next line that would have been read, so this can be
+/// persisted and set as a Chaser's `resume_from` to pick up where things left off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct ChaseState {
+    /// The file that was being chased; None if it was never opened
+    pub file_id: Option<FileId>,
+    /// Next line to be read
+    pub line: Line,
+    /// Byte-position of the next line to be read
+    pub pos: Pos,
+    /// How many lines have been read
+    pub lines_read: usize,
+    /// How many file rotations have been seen, i.e. the generation of the file being
+    /// chased
+    pub rotations: usize,
+    /// Sequence number of the next Record to be delivered
+    #[cfg_attr(feature = "with-serde", serde(default))]
+    pub seq: u64,
+}
+
+/// A line that was read, along with everything we know about where and when it was read
+/// from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct Record {
+    /// The line itself, without its trailing newline
+    pub text: String,
+    /// Line number in the file it was read from
+    pub line: Line,
+    /// Byte-position of the line in the file it was read from
+    pub pos: Pos,
+    /// Path of the file being chased
+    pub path: PathBuf,
+    /// The file the line was read from, which changes whenever the file gets rotated
+    pub file_id: FileId,
+    /// How many bytes were read for this line, including its trailing newline
+    pub len: u64,
+    /// How many file rotations had been seen when the line was read. Together with
+    /// `line`, this identifies the line for as long as the chaser keeps going.
+    pub generation: usize,
+    /// Goes up by one with every Record delivered, across rotations, and carries on
+    /// from where it was when resuming from a ChaseState.
+    pub seq: u64,
+    /// When the line was read
+    pub read_at: SystemTime,
+    /// Whether the line was read without a trailing newline, i.e. it had only been partly
+    /// written at the time
+    pub partial: bool,
+    /// When the line says it was written, going by the chaser's TimestampFormats. None if
+    /// there are none, or none of them found a timestamp in the line (such lines are
+    /// counted on the ControlHandle, see `ControlHandle::untimestamped_lines`).
+    pub timestamp: Option<SystemTime>,
+    /// The fields the chaser's Grok found in the line
+    pub fields: BTreeMap<String, String>,
+    /// Whether the chaser has a Grok and the line didn't match it (such lines are counted
+    /// on the ControlHandle, see `ControlHandle::unmatched_lines`)
+    pub unmatched: bool,
+}
+
+/// In async modes, what to do with a line when the receiving end isn't keeping up and the
+/// buffer is full.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum OverflowPolicy {
+    /// Stop reading the file until there is room (back-pressure)
+    Block,
+    /// Drop the line that was just read
+    DropNewest,
+    /// Drop the oldest buffered line to make room
+    DropOldest,
+    /// Write the line to a file at the given path, to be delivered once the receiving end
+    /// catches up
+    SpillToDisk(PathBuf),
+}
+
+/// What to do when something goes wrong with a line.
+///
+/// Lines that get skipped are reported on the ControlHandle chasing is done with (see
+/// `ControlHandle::skipped_lines` and `ControlHandle::take_skipped`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum ErrorPolicy {
+    /// Stop chasing, handing back the error
+    Stop,
+    /// Skip the line and carry on with the next one
+    Skip,
+    /// Try the line again, up to the given number of times, waiting for `backoff` before
+    /// the first retry and twice as long before each one after that. If it still fails,
+    /// stop chasing, handing back the error.
+    Retry { attempts: usize, backoff: Duration },
+}
+
+/// Rules for which lines get delivered, applied as lines are read, so that lines that
+/// are filtered out never leave the chaser. They are counted on the ControlHandle
+/// chasing is done with (see `ControlHandle::filtered_lines`).
+#[cfg(feature = "filter")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct Filter {
+    /// Regexes a line has to match at least one of to be delivered; if there are none,
+    /// every line is
+    pub include: Vec<String>,
+    /// Regexes a line must not match any of to be delivered
+    pub exclude: Vec<String>,
+    /// Whether the regexes ignore case
+    pub case_insensitive: bool,
+}
+
+/// Limits on how fast lines get delivered, applied as lines are read. Each limit is a
+/// token bucket that holds up to a second's worth of lines or bytes, and starts out full,
+/// so short bursts go through untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct RateLimit {
+    /// How many lines a second can be delivered (at least 1)
+    pub lines_per_second: Option<u64>,
+    /// How many bytes a second can be delivered (at least 1), counting trailing newlines.
+    /// Lines bigger than this still get through, once the bucket is full.
+    pub bytes_per_second: Option<u64>,
+    /// What to do with lines over the limit
+    pub policy: RateLimitPolicy,
+}
+
+/// What to do with lines that are over a RateLimit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum RateLimitPolicy {
+    /// Stop reading the file until the line can be delivered (back-pressure)
+    Block,
+    /// Drop the line, counting it on the ControlHandle chasing is done with (see
+    /// `ControlHandle::rate_limited_lines`)
+    Drop,
+}
+
+/// Which lines get delivered, when only some of them are wanted. Lines that are sampled
+/// out are counted on the ControlHandle chasing is done with (see
+/// `ControlHandle::sampled_out_lines`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum Sampling {
+    /// Deliver the first line and every Nth one after that
+    OneIn(u64),
+    /// Deliver lines whose hash is divisible by N, so roughly 1 in N of them, and always
+    /// the same ones: a line that is delivered once is delivered every time it shows up
+    Hash(u64),
+}
+
+/// Rules for redacting secrets and personal data, applied as lines are read, so that they
+/// never leave the chaser. Lines that had something redacted are counted on the
+/// ControlHandle chasing is done with (see `ControlHandle::redacted_lines`).
+#[cfg(feature = "redact")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct Redaction {
+    /// Built-in detectors for what to redact
+    pub detectors: Vec<Detector>,
+    /// Regexes for anything else to redact. If one has a group named `redact`, only what
+    /// that group matches is redacted, e.g. `password=(?P<redact>\S+)`.
+    pub patterns: Vec<String>,
+    /// What to replace things with
+    pub replace_with: Replacement,
+}
+
+/// Built-in ways of finding things to redact
+#[cfg(feature = "redact")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum Detector {
+    /// JSON Web Tokens, such as bearer tokens
+    Jwt,
+    /// AWS access key IDs, and secret access keys that are labelled as such
+    AwsKey,
+    /// Email addresses
+    Email,
+    /// Credit card numbers (13 to 19 digits, maybe split up by spaces or dashes) that pass
+    /// a Luhn check
+    CreditCard,
+    /// IPv4 and IPv6 addresses
+    Ip,
+}
+
+#[cfg(feature = "redact")]
+impl Detector {
+    /// Every built-in detector
+    pub fn all() -> Vec<Detector> {
+        vec![
+            Detector::Jwt,
+            Detector::AwsKey,
+            Detector::Email,
+            Detector::CreditCard,
+            Detector::Ip,
+        ]
+    }
+}
+
+/// What redacted things are replaced with
+#[cfg(feature = "redact")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum Replacement {
+    /// The given text
+    Mask(String),
+    /// The first 16 hex digits of the HMAC-SHA256 of what was redacted, using the given
+    /// key, so that lines with the same values in them can still be matched up
+    KeyedHash(String),
+}
+
+/// A grok-style pattern for pulling named fields out of lines, i.e. a regex that can refer
+/// to named patterns with `%{NAME}`, or `%{NAME:field}` to capture what they match as a
+/// field. See `GROK_PATTERNS` for the built-in ones.
+///
+/// ```
+/// # extern crate chase;
+/// # use chase::*;
+/// # fn main () {
+/// let mut chaser = Chaser::new("access.log");
+/// chaser.grok = Some(Grok {
+///     pattern: "%{IP:client} %{WORD:method} %{URIPATHPARAM:request} %{MILLIS:took}".to_string(),
+///     definitions: vec![("MILLIS".to_string(), "%{NUMBER}ms".to_string())],
+/// });
+/// # }
+/// ```
+#[cfg(feature = "grok")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub struct Grok {
+    /// The pattern lines are matched against; it isn't anchored unless it says so
+    pub pattern: String,
+    /// Named patterns of your own, which take precedence over built-in ones with the same
+    /// name
+    pub definitions: Vec<(String, String)>,
+}
 
 /// Your entry point for following a file.
 #[derive(Debug, Clone)]
@@ -27,6 +278,12 @@ pub(crate) struct FileId(pub(crate) u64);
 pub struct Chaser {
     /// Line to start chasing from
     pub line: Line,
+    /// Start chasing from the first line with a timestamp at or after this instead of from
+    /// `line`, going by `timestamps` (or RFC3339 and syslog timestamps if there are none).
+    /// The file is binary searched, so its timestamps should only ever go up. Ignored when
+    /// resuming from a ChaseState.
+    #[cfg(feature = "timestamps")]
+    pub since: Option<SystemTime>,
     /// Path of the file you want to chase
     pub path: PathBuf,
     /// When we start running and there is no file and/or file info to be read, how long to
@@ -44,6 +301,46 @@ pub struct Chaser {
     /// After we read a file to its end, how long to wait before trying to read the next line
     /// again.
     pub not_rotated_wait: Duration,
+    /// Whether to keep waiting for more lines once we get to the end of the file. If not,
+    /// chasing stops the first time we do, like `cat` rather than `tail -f`.
+    pub follow: bool,
+    /// State to resume chasing from, e.g. one handed back by a previous chasing thread. If
+    /// the file at `path` is still the same one, chasing picks up from the state's position
+    /// and counters; otherwise we fall back to starting from `line`.
+    pub resume_from: Option<ChaseState>,
+    /// In async modes, how many lines can be buffered ahead of the receiving end. 0 means
+    /// lines are handed over one at a time, though policies other than Block always buffer
+    /// at least one line.
+    pub channel_capacity: usize,
+    /// In async modes, what to do when the buffer is full.
+    pub overflow_policy: OverflowPolicy,
+    /// What to do when the callback given to a sync mode returns an error
+    pub on_callback_error: ErrorPolicy,
+    /// What to do when reading fails in a way that may well go away if tried again, i.e.
+    /// with EINTR, ESTALE or EIO. Skipping such a line means carrying on as if the end of
+    /// the file had been reached, so it is read again after `not_rotated_wait`. Other read
+    /// errors always stop chasing.
+    pub on_read_error: ErrorPolicy,
+    /// What to do when a line is not valid UTF-8
+    pub on_decoding_error: ErrorPolicy,
+    /// Which lines get delivered
+    #[cfg(feature = "filter")]
+    pub filter: Filter,
+    /// How to find the timestamps of lines, for Records. Each is tried in turn until one
+    /// finds a timestamp.
+    #[cfg(feature = "timestamps")]
+    pub timestamps: Vec<TimestampFormat>,
+    /// Pattern for pulling fields out of lines, for Records
+    #[cfg(feature = "grok")]
+    pub grok: Option<Grok>,
+    /// Which lines get delivered, after `filter`
+    pub sampling: Option<Sampling>,
+    /// How fast lines get delivered, after `sampling`
+    pub rate_limit: Option<RateLimit>,
+    /// What gets redacted from lines before they are delivered. This comes after
+    /// `filter`, which sees lines as they are in the file.
+    #[cfg(feature = "redact")]
+    pub redaction: Option<Redaction>,
 }
 
 #[derive(Debug)]
@@ -54,6 +351,87 @@ pub(crate) struct Chasing<'a> {
     pub(crate) buffer: String,
     pub(crate) line: Line,
     pub(crate) pos: Pos,
+    pub(crate) lines_read: usize,
+    pub(crate) rotations: usize,
+    pub(crate) seq: u64,
+    /// How many times in a row reading the current line has been retried
+    pub(crate) read_retries: usize,
+    /// Set once we've seen the file get rotated, while we finish reading the old one
+    pub(crate) rotated_to: Option<(File, FileId)>,
+    pub(crate) handle: ControlHandle,
+    #[cfg(feature = "filter")]
+    pub(crate) filter: Option<Matcher>,
+    #[cfg(feature = "timestamps")]
+    pub(crate) timestamps: Option<Extractor>,
+    #[cfg(feature = "grok")]
+    pub(crate) grok: Option<GrokMatcher>,
+    pub(crate) sampler: Option<Sampler>,
+    pub(crate) limiter: Option<Limiter>,
+    #[cfg(feature = "redact")]
+    pub(crate) redactor: Option<Redactor>,
+    /// Set when the line that was just read was skipped rather than delivered (e.g. by an
+    /// UnparseablePolicy), so that it doesn't get a sequence number
+    pub(crate) undelivered: Cell<bool>,
+    /// How many lines have been read since the state was last published as the handle's
+    /// checkpoint
+    pub(crate) unchecked_lines: usize,
+    /// Where the lines the deliverer is holding on to rather than having handed off yet
+    /// start, if there are any
+    pub(crate) held_from: Option<ChaseState>,
+}
+
+impl<'a> Chasing<'a> {
+    pub(crate) fn state(&self) -> ChaseState {
+        ChaseState {
+            file_id: Some(self.file_id),
+            line: self.line,
+            pos: self.pos,
+            lines_read: self.lines_read,
+            rotations: self.rotations,
+            seq: self.seq,
+        }
+    }
+
+    /// The state to resume from without missing any lines, i.e. the current one unless
+    /// the deliverer is still holding on to some
+    pub(crate) fn handed_off(&self) -> ChaseState {
+        match self.held_from {
+            Some(ref held_from) => held_from.clone(),
+            None => self.state(),
+        }
+    }
+
+    /// The line that was just read, without its trailing newline
+    pub(crate) fn text(&self) -> &str {
+        self.buffer.trim_end_matches('\n')
+    }
+
+    /// A Record of the line that was just read
+    pub(crate) fn record(&self, bytes_read: usize) -> Record {
+        #[cfg(feature = "grok")]
+        let (fields, unmatched) = self.grok_fields();
+        #[cfg(not(feature = "grok"))]
+        let (fields, unmatched) = (BTreeMap::new(), false);
+        #[cfg(feature = "timestamps")]
+        let timestamp = self.timestamp();
+        #[cfg(not(feature = "timestamps"))]
+        let timestamp = None;
+        Record {
+            text: self.text().to_string(),
+            line: self.line,
+            pos: self.pos,
+            path: self.chaser.path.clone(),
+            file_id: self.file_id,
+            len: bytes_read as u64,
+            generation: self.rotations,
+            seq: self.seq,
+            read_at: SystemTime::now(),
+            partial: !self.buffer.ends_with('\n'),
+            timestamp,
+            fields,
+            unmatched,
+        }
+    }
 }
 
 impl Chaser {
@@ -64,12 +442,31 @@ impl Chaser {
     {
         Chaser {
             line: Line(0),
+            #[cfg(feature = "timestamps")]
+            since: None,
             path: path.into(),
             initial_no_file_attempts: None,
             initial_no_file_wait: Duration::from_millis(DEFAULT_ROTATION_CHECK_WAIT_MILLIS),
             rotation_check_attempts: None,
             rotation_check_wait: Duration::from_millis(DEFAULT_ROTATION_CHECK_WAIT_MILLIS),
             not_rotated_wait: Duration::from_millis(DEFAULT_NOT_ROTATED_WAIT_MILLIS),
+            follow: true,
+            resume_from: None,
+            channel_capacity: 0,
+            overflow_policy: OverflowPolicy::Block,
+            on_callback_error: ErrorPolicy::Stop,
+            on_read_error: ErrorPolicy::Stop,
+            on_decoding_error: ErrorPolicy::Stop,
+            #[cfg(feature = "filter")]
+            filter: Filter::default(),
+            #[cfg(feature = "timestamps")]
+            timestamps: Vec::new(),
+            #[cfg(feature = "grok")]
+            grok: None,
+            sampling: None,
+            rate_limit: None,
+            #[cfg(feature = "redact")]
+            redaction: None,
         }
     }
 }
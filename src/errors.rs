@@ -1,6 +1,7 @@
 //! Module holding various error wrappers
 
 use std::io;
+use std::str::Utf8Error;
 
 use std::sync::mpsc as channel_mpsc;
 
@@ -9,50 +10,158 @@ use futures::sync::mpsc as stream_mpsc;
 
 use std::fmt;
 use std::error::Error;
-use async::SendData;
+use std::path::PathBuf;
+use data::{ChaseState, Line, Pos};
 
 #[derive(Debug)]
 pub enum ChaseError {
+    /// Reading the file failed
     IoError(io::Error),
-    ChannelSendError(channel_mpsc::SendError<SendData>),
-    #[cfg(feature = "stream")] StreamSendError(stream_mpsc::SendError<SendData>),
-    Custom(Box<Error + Send + Sync>),
+    /// The file could not be found, even after trying `initial_no_file_attempts` times
+    FileNotFound { path: PathBuf, attempts: usize },
+    /// We could not check whether the file had been rotated, even after trying
+    /// `rotation_check_attempts` times. Line and Pos are where we were in the file we
+    /// were already reading.
+    RotationCheckExhausted {
+        path: PathBuf,
+        attempts: usize,
+        line: Line,
+        pos: Pos,
+        source: io::Error,
+    },
+    /// The line at the given Line and Pos is not valid UTF-8
+    InvalidEncoding {
+        line: Line,
+        pos: Pos,
+        source: Utf8Error,
+    },
+    /// The callback given to a sync mode returned an error while handling the line at the
+    /// given Line and Pos (for batches, the first line of the batch)
+    CallbackError {
+        line: Line,
+        pos: Pos,
+        error: Box<ChaseError>,
+    },
+    /// In async modes, the receiving end of the channel or stream is gone
+    ReceiverDropped,
+    /// The line at the given Line and Pos could not be parsed (e.g. as JSON)
+    Unparseable {
+        line: Line,
+        pos: Pos,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// One of the chaser's patterns (e.g. a Filter regex or a strftime TimestampFormat)
+    /// could not be compiled
+    InvalidPattern {
+        pattern: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    Custom(Box<dyn Error + Send + Sync>),
 }
 
 impl fmt::Display for ChaseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::ChaseError::*;
-        match self {
-            &IoError(ref e) => write!(f, "{}", e),
-            &ChannelSendError(ref e) => write!(f, "{}", e),
-            #[cfg(feature = "stream")]
-            &StreamSendError(ref e) => write!(f, "{}", e),
-            &Custom(ref e) => e.fmt(f),
+        match *self {
+            IoError(ref e) => write!(f, "{}", e),
+            FileNotFound { ref path, attempts } => write!(
+                f,
+                "Could not find {} after {} attempts",
+                path.display(),
+                attempts
+            ),
+            RotationCheckExhausted {
+                ref path,
+                attempts,
+                line,
+                pos,
+                ref source,
+            } => write!(
+                f,
+                "Could not check {} for rotation after {} attempts, at line {} (byte {}): {}",
+                path.display(),
+                attempts,
+                line.0,
+                pos.0,
+                source
+            ),
+            InvalidEncoding {
+                line,
+                pos,
+                ref source,
+            } => write!(
+                f,
+                "Line {} (byte {}) is not valid UTF-8: {}",
+                line.0,
+                pos.0,
+                source
+            ),
+            CallbackError {
+                line,
+                pos,
+                ref error,
+            } => write!(
+                f,
+                "Callback failed on line {} (byte {}): {}",
+                line.0,
+                pos.0,
+                error
+            ),
+            ReceiverDropped => write!(f, "The receiving end has been dropped"),
+            Unparseable {
+                line,
+                pos,
+                ref source,
+            } => write!(
+                f,
+                "Could not parse line {} (byte {}): {}",
+                line.0,
+                pos.0,
+                source
+            ),
+            InvalidPattern {
+                ref pattern,
+                ref source,
+            } => write!(f, "Invalid pattern {:?}: {}", pattern, source),
+            Custom(ref e) => e.fmt(f),
         }
     }
 }
 
 impl Error for ChaseError {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         use self::ChaseError::*;
-        match self {
-            &IoError(ref e) => e.description(),
-            &ChannelSendError(ref e) => e.description(),
-            #[cfg(feature = "stream")]
-            &StreamSendError(ref e) => e.description(),
-            &Custom(ref e) => e.description(),
+        match *self {
+            IoError(ref e) => Some(e),
+            FileNotFound { .. } | ReceiverDropped => None,
+            RotationCheckExhausted { ref source, .. } => Some(source),
+            InvalidEncoding { ref source, .. } => Some(source),
+            InvalidPattern { ref source, .. } => Some(source.as_ref()),
+            Unparseable { ref source, .. } => Some(source.as_ref()),
+            CallbackError { ref error, .. } => Some(error.as_ref()),
+            Custom(ref e) => Some(e.as_ref()),
         }
     }
+}
 
-    fn cause(&self) -> Option<&Error> {
-        use self::ChaseError::*;
-        match self {
-            &IoError(ref e) => Some(e),
-            &ChannelSendError(ref e) => Some(e),
-            #[cfg(feature = "stream")]
-            &StreamSendError(ref e) => Some(e),
-            &Custom(ref e) => e.cause(),
-        }
+/// A ChaseError, along with the state the chaser was in when it happened, so that
+/// chasing can be resumed afterwards. If lines were being held on to in a batch or run
+/// that never got delivered, the state is from before them, so they are read again.
+#[derive(Debug)]
+pub struct ChaseFailure {
+    pub error: ChaseError,
+    pub state: ChaseState,
+}
+
+impl fmt::Display for ChaseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Error for ChaseFailure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
     }
 }
 
@@ -62,15 +171,16 @@ impl From<io::Error> for ChaseError {
     }
 }
 
-impl From<channel_mpsc::SendError<SendData>> for ChaseError {
-    fn from(e: channel_mpsc::SendError<SendData>) -> Self {
-        ChaseError::ChannelSendError(e)
+// Whatever failed to be sent is dropped, rather than carried around in the error
+impl<T> From<channel_mpsc::SendError<T>> for ChaseError {
+    fn from(_: channel_mpsc::SendError<T>) -> Self {
+        ChaseError::ReceiverDropped
     }
 }
 
 #[cfg(feature = "stream")]
-impl From<stream_mpsc::SendError<SendData>> for ChaseError {
-    fn from(e: stream_mpsc::SendError<SendData>) -> Self {
-        ChaseError::StreamSendError(e)
+impl<T> From<stream_mpsc::SendError<T>> for ChaseError {
+    fn from(_: stream_mpsc::SendError<T>) -> Self {
+        ChaseError::ReceiverDropped
     }
 }
@@ -0,0 +1,108 @@
+//! Holds logic for filtering lines as they are read, before they are delivered
+
+use data::Filter;
+use errors::ChaseError;
+
+use regex::{RegexBuilder, RegexSet, RegexSetBuilder};
+
+/// A compiled Filter
+#[derive(Debug)]
+pub(crate) struct Matcher {
+    include: Option<RegexSet>,
+    exclude: Option<RegexSet>,
+}
+
+impl Filter {
+    /// Compiles the filter's regexes, giving back None if it lets every line through
+    pub(crate) fn compile(&self) -> Result<Option<Matcher>, ChaseError> {
+        let include = regex_set(&self.include, self.case_insensitive)?;
+        let exclude = regex_set(&self.exclude, self.case_insensitive)?;
+        if include.is_none() && exclude.is_none() {
+            Ok(None)
+        } else {
+            Ok(Some(Matcher { include, exclude }))
+        }
+    }
+}
+
+impl Matcher {
+    /// Whether or not the given line should be delivered
+    pub(crate) fn keeps(&self, text: &str) -> bool {
+        self.include
+            .as_ref()
+            .map(|include| include.is_match(text))
+            .unwrap_or(true)
+            && !self.exclude
+                .as_ref()
+                .map(|exclude| exclude.is_match(text))
+                .unwrap_or(false)
+    }
+}
+
+fn regex_set(patterns: &[String], case_insensitive: bool) -> Result<Option<RegexSet>, ChaseError> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    RegexSetBuilder::new(patterns)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map(Some)
+        .map_err(|source| {
+            // Point out which of the patterns is the problem, if we can
+            let pattern = patterns
+                .iter()
+                .find(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(case_insensitive)
+                        .build()
+                        .is_err()
+                })
+                .cloned()
+                .unwrap_or_else(|| patterns.join(" | "));
+            ChaseError::InvalidPattern {
+                pattern,
+                source: Box::new(source),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use data::*;
+    use errors::ChaseError;
+
+    fn filter(include: &[&str], exclude: &[&str], case_insensitive: bool) -> Filter {
+        Filter {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            case_insensitive,
+        }
+    }
+
+    #[test]
+    fn matcher_test() {
+        assert!(Filter::default().compile().unwrap().is_none());
+
+        let matcher = filter(&["error", "warn"], &["healthcheck"], false)
+            .compile()
+            .unwrap()
+            .unwrap();
+        assert!(matcher.keeps("an error happened"));
+        assert!(matcher.keeps("warning: disk is filling up"));
+        assert!(!matcher.keeps("all is well"));
+        assert!(!matcher.keeps("error in healthcheck"));
+        assert!(!matcher.keeps("ERROR happened"));
+
+        let matcher = filter(&[], &["^debug"], true).compile().unwrap().unwrap();
+        assert!(matcher.keeps("info: hi"));
+        assert!(!matcher.keeps("DEBUG: hi"));
+    }
+
+    #[test]
+    fn invalid_pattern_test() {
+        match filter(&["fine", "(broken"], &[], false).compile() {
+            Err(ChaseError::InvalidPattern { pattern, .. }) => assert_eq!(pattern, "(broken"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
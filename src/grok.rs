@@ -0,0 +1,369 @@
+//! Holds logic for pulling named fields out of lines with grok-style patterns, i.e. regexes
+//! made up of reusable named patterns, like `%{IP:client} %{WORD:method}`.
+
+use data::*;
+use errors::ChaseError;
+
+use regex::Regex;
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+/// How deeply named patterns can refer to other named patterns, which is mostly there to
+/// catch patterns that refer to themselves
+const MAX_DEPTH: usize = 32;
+
+/// The named patterns every Grok can use, unless it defines its own with the same name.
+///
+/// These are mostly the same as Logstash's, minus a few that rely on look-arounds.
+pub const GROK_PATTERNS: &[(&str, &str)] = &[
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    ("INT", r"[+-]?[0-9]+"),
+    ("BASE10NUM", r"[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)"),
+    ("NUMBER", r"%{BASE10NUM}"),
+    ("BASE16NUM", r"[+-]?(?:0x)?[0-9A-Fa-f]+"),
+    ("POSINT", r"\b[1-9][0-9]*\b"),
+    ("NONNEGINT", r"\b[0-9]+\b"),
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'"#),
+    ("UUID", r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}"),
+    (
+        "IPV4",
+        r"(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])",
+    ),
+    (
+        "IPV6",
+        r"\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b|\b[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6}::(?:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6}\b)?",
+    ),
+    ("IP", r"%{IPV6}|%{IPV4}"),
+    (
+        "HOSTNAME",
+        r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b",
+    ),
+    ("IPORHOST", r"%{IP}|%{HOSTNAME}"),
+    ("HOSTPORT", r"%{IPORHOST}:%{POSINT}"),
+    ("UNIXPATH", r"(?:/[\w%!$@:.,+~-]*)+"),
+    ("PATH", r"%{UNIXPATH}"),
+    ("URIPROTO", r"[A-Za-z][A-Za-z0-9+.-]+"),
+    ("URIHOST", r"%{IPORHOST}(?::%{POSINT})?"),
+    ("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_-]*)+"),
+    ("URIPARAM", r"\?[A-Za-z0-9$.+!*'|(){},~@#%&/=:;_?\[\]<>-]*"),
+    ("URIPATHPARAM", r"%{URIPATH}(?:%{URIPARAM})?"),
+    (
+        "URI",
+        r"%{URIPROTO}://(?:%{USER}(?::[^@]*)?@)?(?:%{URIHOST})?(?:%{URIPATHPARAM})?",
+    ),
+    (
+        "MONTH",
+        r"\b(?:Jan(?:uary)?|Feb(?:ruary)?|Mar(?:ch)?|Apr(?:il)?|May|June?|July?|Aug(?:ust)?|Sep(?:tember)?|Oct(?:ober)?|Nov(?:ember)?|Dec(?:ember)?)\b",
+    ),
+    ("MONTHNUM", r"0?[1-9]|1[0-2]"),
+    ("MONTHDAY", r"0[1-9]|[12][0-9]|3[01]|[1-9]"),
+    ("YEAR", r"[0-9]{4}"),
+    ("HOUR", r"2[0123]|[01]?[0-9]"),
+    ("MINUTE", r"[0-5][0-9]"),
+    ("SECOND", r"(?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?"),
+    ("TIME", r"%{HOUR}:%{MINUTE}(?::%{SECOND})?"),
+    ("ISO8601_TIMEZONE", r"Z|[+-]%{HOUR}(?::?%{MINUTE})"),
+    (
+        "TIMESTAMP_ISO8601",
+        r"%{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?",
+    ),
+    ("HTTPDATE", r"%{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}"),
+    ("SYSLOGTIMESTAMP", r"%{MONTH} +%{MONTHDAY} %{TIME}"),
+    (
+        "LOGLEVEL",
+        r"[Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo|INFO|[Ww]arn(?:ing)?|WARN(?:ING)?|[Ee]rr(?:or)?|ERR(?:OR)?|[Cc]rit(?:ical)?|CRIT(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|[Ee]merg(?:ency)?|EMERG(?:ENCY)?",
+    ),
+    (
+        "COMMONAPACHELOG",
+        r#"%{IPORHOST:clientip} %{USER:ident} %{USER:auth} \[%{HTTPDATE:timestamp}\] "(?:%{WORD:verb} %{NOTSPACE:request}(?: HTTP/%{NUMBER:httpversion})?|%{DATA:rawrequest})" %{NUMBER:response} (?:%{NUMBER:bytes}|-)"#,
+    ),
+    (
+        "COMBINEDAPACHELOG",
+        r"%{COMMONAPACHELOG} %{QUOTEDSTRING:referrer} %{QUOTEDSTRING:agent}",
+    ),
+];
+
+/// A compiled Grok
+#[derive(Debug)]
+pub(crate) struct GrokMatcher {
+    regex: Regex,
+    /// The name of the field each of the regex's named groups is for
+    fields: Vec<(String, String)>,
+}
+
+impl Grok {
+    /// A Grok with the given pattern, using only the built-in named patterns
+    pub fn new<S>(pattern: S) -> Grok
+    where
+        S: Into<String>,
+    {
+        Grok {
+            pattern: pattern.into(),
+            definitions: Vec::new(),
+        }
+    }
+
+    /// Expands the pattern's named patterns and compiles the resulting regex
+    pub(crate) fn compile(&self) -> Result<GrokMatcher, ChaseError> {
+        let mut definitions: HashMap<&str, &str> = GROK_PATTERNS.iter().cloned().collect();
+        for (name, definition) in &self.definitions {
+            definitions.insert(name, definition);
+        }
+        let invalid = |source: Box<dyn Error + Send + Sync>| ChaseError::InvalidPattern {
+            pattern: self.pattern.clone(),
+            source,
+        };
+        let reference = Regex::new(r"%\{(\w+)(?::([^:}]+))?(?::[^}]*)?\}")
+            .expect("The grok reference pattern is valid");
+        let mut fields = Vec::new();
+        let expanded = expand(&self.pattern, &reference, &definitions, 0, &mut fields)
+            .map_err(|e| invalid(Box::new(e)))?;
+        let regex = Regex::new(&expanded).map_err(|e| invalid(Box::new(e)))?;
+        Ok(GrokMatcher { regex, fields })
+    }
+}
+
+impl GrokMatcher {
+    /// The fields found in the line, or None if it doesn't match. Where a field is named
+    /// more than once, the first part of the pattern that matched wins.
+    pub(crate) fn fields(&self, text: &str) -> Option<BTreeMap<String, String>> {
+        let captures = self.regex.captures(text)?;
+        let mut found = BTreeMap::new();
+        for (group, field) in &self.fields {
+            if let Some(matched) = captures.name(group) {
+                found
+                    .entry(field.clone())
+                    .or_insert_with(|| matched.as_str().to_string());
+            }
+        }
+        Some(found)
+    }
+}
+
+impl<'a> Chasing<'a> {
+    /// The fields found in the line that was just read by the chaser's Grok, and whether
+    /// it failed to match. Lines it fails to match are counted on the ControlHandle.
+    pub(crate) fn grok_fields(&self) -> (BTreeMap<String, String>, bool) {
+        match self.grok.as_ref().map(|grok| grok.fields(self.text())) {
+            Some(Some(fields)) => (fields, false),
+            Some(None) => {
+                self.handle.count_unmatched_lines(1);
+                (BTreeMap::new(), true)
+            }
+            None => (BTreeMap::new(), false),
+        }
+    }
+}
+
+/// Why a Grok's pattern could not be expanded
+#[derive(Debug)]
+enum GrokError {
+    UnknownPattern(String),
+    TooDeep(String),
+}
+
+impl fmt::Display for GrokError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GrokError::UnknownPattern(ref name) => write!(f, "Unknown pattern %{{{}}}", name),
+            GrokError::TooDeep(ref name) => write!(
+                f,
+                "Patterns nested more than {} deep at %{{{}}}; does it refer to itself?",
+                MAX_DEPTH,
+                name
+            ),
+        }
+    }
+}
+
+impl Error for GrokError {}
+
+// Replaces every %{NAME} and %{NAME:field} in the pattern with the regex they stand for,
+// turning the latter into named groups and noting down which field each one is for. A
+// third part, like in %{NUMBER:bytes:int}, is allowed but ignored; all fields are strings.
+fn expand(
+    pattern: &str,
+    reference: &Regex,
+    definitions: &HashMap<&str, &str>,
+    depth: usize,
+    fields: &mut Vec<(String, String)>,
+) -> Result<String, GrokError> {
+    let mut expanded = String::new();
+    let mut last = 0;
+    for captures in reference.captures_iter(pattern) {
+        let whole = captures.get(0).expect("Group 0 is always there");
+        expanded.push_str(&pattern[last..whole.start()]);
+        last = whole.end();
+        let name = &captures[1];
+        if depth >= MAX_DEPTH {
+            return Err(GrokError::TooDeep(name.to_string()));
+        }
+        let definition = definitions
+            .get(name)
+            .ok_or_else(|| GrokError::UnknownPattern(name.to_string()))?;
+        let inner = expand(definition, reference, definitions, depth + 1, fields)?;
+        match captures.get(2) {
+            Some(field) => {
+                let group = format!("grok{}", fields.len());
+                expanded.push_str(&format!("(?P<{}>{})", group, inner));
+                fields.push((group, field.as_str().to_string()));
+            }
+            None => expanded.push_str(&format!("(?:{})", inner)),
+        }
+    }
+    expanded.push_str(&pattern[last..]);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use control::*;
+    use errors::ChaseError;
+    use grok::*;
+    use sync::EachRecord;
+    use tempdir::*;
+    use std::io::Write;
+
+    use std::fs::OpenOptions;
+
+    fn fields(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn fields_test() {
+        let grok = Grok::new("%{IP:client} %{WORD:method} %{URIPATHPARAM:request} %{NUMBER:bytes}")
+            .compile()
+            .unwrap();
+        assert_eq!(
+            grok.fields("55.3.244.1 GET /index.html?a=b 15824 0.043"),
+            Some(fields(&[
+                ("client", "55.3.244.1"),
+                ("method", "GET"),
+                ("request", "/index.html?a=b"),
+                ("bytes", "15824"),
+            ]))
+        );
+        assert_eq!(grok.fields("nope"), None);
+
+        let combined = Grok::new("^%{COMBINEDAPACHELOG}$").compile().unwrap();
+        let found = combined
+            .fields(
+                r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://example.com/start.html" "Mozilla/4.08""#,
+            )
+            .unwrap();
+        assert_eq!(found["clientip"], "127.0.0.1");
+        assert_eq!(found["auth"], "frank");
+        assert_eq!(found["timestamp"], "10/Oct/2000:13:55:36 -0700");
+        assert_eq!(found["request"], "/apache_pb.gif");
+        assert_eq!(found["bytes"], "2326");
+        assert_eq!(found["agent"], "\"Mozilla/4.08\"");
+        assert!(!found.contains_key("rawrequest"));
+
+        let custom = Grok {
+            pattern: "%{TIMESTAMP_ISO8601:at} %{LEVEL:level}: %{GREEDYDATA:msg}".to_string(),
+            definitions: vec![("LEVEL".to_string(), "%{LOGLEVEL}!*".to_string())],
+        };
+        assert_eq!(
+            custom
+                .compile()
+                .unwrap()
+                .fields("2018-02-03T04:05:06.789+01:00 WARN!!: Disk \"full\""),
+            Some(fields(&[
+                ("at", "2018-02-03T04:05:06.789+01:00"),
+                ("level", "WARN!!"),
+                ("msg", "Disk \"full\""),
+            ]))
+        );
+    }
+
+    #[test]
+    fn ip_test() {
+        let grok = Grok::new("%{IP:client}").compile().unwrap();
+        let client = |text| grok.fields(text).map(|found| found["client"].clone());
+        assert_eq!(
+            client("from 2001:0db8:85a3:0000:0000:8a2e:0370:7334 on"),
+            Some("2001:0db8:85a3:0000:0000:8a2e:0370:7334".to_string())
+        );
+        assert_eq!(
+            client("from 2001:db8::ff00:42:8329 on"),
+            Some("2001:db8::ff00:42:8329".to_string())
+        );
+        assert_eq!(client("from fe80:: on"), Some("fe80::".to_string()));
+        assert_eq!(client("from 10.0.0.1 on"), Some("10.0.0.1".to_string()));
+        // Neither times nor paths are addresses
+        assert_eq!(client("at 13:55:36"), None);
+        assert_eq!(client("in std::io"), None);
+        assert_eq!(client("a bare :: on its own"), None);
+    }
+
+    #[test]
+    fn invalid_grok_test() {
+        match Grok::new("%{IP:client} %{NOPE}").compile() {
+            Err(ChaseError::InvalidPattern { pattern, source }) => {
+                assert_eq!(pattern, "%{IP:client} %{NOPE}");
+                assert_eq!(source.to_string(), "Unknown pattern %{NOPE}");
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let looping = Grok {
+            pattern: "%{LOOP}".to_string(),
+            definitions: vec![("LOOP".to_string(), "a%{LOOP}".to_string())],
+        };
+        assert!(looping.compile().is_err());
+        assert!(Grok::new("%{WORD:a} (").compile().is_err());
+    }
+
+    #[test]
+    fn run_records_grok_test() {
+        let temp_dir = TempDir::new("chase-test-grok").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.grok = Some(Grok::new("^%{LOGLEVEL:level} %{GREEDYDATA:msg}$"));
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "INFO Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        let handle = ControlHandle::new();
+        let mut seen = Vec::new();
+        chaser
+            .run_controlled(
+                &handle,
+                EachRecord(|record: &Record| {
+                    seen.push((record.fields.clone(), record.unmatched));
+                    Ok(if seen.len() < 2 {
+                        Control::Continue
+                    } else {
+                        Control::Stop
+                    })
+                }),
+            )
+            .unwrap();
+        assert_eq!(
+            seen,
+            vec![
+                (fields(&[("level", "INFO"), ("msg", "Hello, world 1")]), false),
+                (BTreeMap::new(), true),
+            ]
+        );
+        assert_eq!(handle.unmatched_lines(), 1);
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! Holds a blocking Iterator implementation of file following.
+
+use data::*;
+use control::*;
+use errors::ChaseError;
+
+use std::time::{Duration, Instant};
+
+impl Chaser {
+    /// Start chasing a file synchronously, as a blocking Iterator over the lines read.
+    ///
+    /// Iterating happens on the calling thread, and rotations are followed the same way as
+    /// with `run`. Iteration only ends after an error, if an idle timeout has been set
+    /// and no lines have come in for that long, or if the chaser is stopped (see
+    /// `lines_with_handle`).
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # use std::time::Duration;
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-iter-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let mut chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// writeln!(file_write, "Hello, world 1").unwrap();
+    /// writeln!(file_write, "Hello, world 2").unwrap();
+    ///
+    /// let mut seen = Vec::new();
+    /// for rec in chaser.lines().unwrap().idle_timeout(Duration::from_millis(100)) {
+    ///     let (line, num, _) = rec.unwrap();
+    ///     seen.push((line, num));
+    /// }
+    ///
+    /// assert_eq!(seen, vec![
+    ///     ("Hello, world 1".to_string(), Line(0)),
+    ///     ("Hello, world 2".to_string(), Line(1)),
+    /// ]);
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn lines<'a>(&'a mut self) -> Result<Lines<'a>, ChaseError> {
+        self.lines_with_handle(&ControlHandle::new())
+    }
+
+    /// Start chasing a file synchronously, like `lines`, but with a ControlHandle that can
+    /// be used from other threads to control the chaser and stop it (in which case
+    /// iteration ends), and on which lines skipped because of an ErrorPolicy are reported.
+    pub fn lines_with_handle<'a>(
+        &'a mut self,
+        handle: &ControlHandle,
+    ) -> Result<Lines<'a>, ChaseError> {
+        let initial_state = self.initial_state();
+        let running = self.start_chasing(handle, &initial_state)?;
+        Ok(Lines {
+            done: running.is_none(),
+            running,
+            idle_timeout: None,
+            last_line_at: Instant::now(),
+        })
+    }
+}
+
+/// A blocking Iterator over the lines of a file being chased, created with
+/// `Chaser::lines`.
+#[derive(Debug)]
+pub struct Lines<'a> {
+    running: Option<Chasing<'a>>,
+    done: bool,
+    idle_timeout: Option<Duration>,
+    last_line_at: Instant,
+}
+
+impl<'a> Lines<'a> {
+    /// Ends iteration once no new lines have been read for the given duration.
+    ///
+    /// This is checked whenever the end of the file is reached, so iteration can go on
+    /// for up to the chaser's `not_rotated_wait` longer than the timeout.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Lines<'a> {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// The state of the chaser, pointing at the next line to be read. This is still
+    /// available after iteration has ended.
+    pub fn state(&self) -> Option<ChaseState> {
+        self.running.as_ref().map(|running| running.state())
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Result<(String, Line, Pos), ChaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let idle_timeout = self.idle_timeout;
+        let last_line_at = self.last_line_at;
+        let result = {
+            let running = self.running.as_mut()?;
+            running
+                .next_line(&mut |_| match idle_timeout {
+                    Some(timeout) if last_line_at.elapsed() >= timeout => Ok(Control::Stop),
+                    _ => Ok(Control::Continue),
+                })
+                .and_then(|maybe_bytes_read| match maybe_bytes_read {
+                    Some(bytes_read) => {
+                        let read = (running.text().to_string(), running.line, running.pos);
+                        running.advance(bytes_read)?;
+                        Ok(Some(read))
+                    }
+                    None => Ok(None),
+                })
+        };
+        match result {
+            Ok(Some(read)) => {
+                self.last_line_at = Instant::now();
+                Some(Ok(read))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data::*;
+    use control::*;
+    use tempdir::*;
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+
+    use std::fs::{rename, OpenOptions};
+
+    #[test]
+    fn lines_test() {
+        let temp_dir = TempDir::new("chase-test-iter").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.line = Line(1);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        let rotating_path = file_path.clone();
+        let rotated_path = temp_dir.path().join("test.log.bk");
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            writeln!(file_write, "Hello, world 3").unwrap();
+            rename(&rotating_path, &rotated_path).unwrap();
+            let mut file_write_new = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&rotating_path)
+                .unwrap();
+            writeln!(file_write_new, "Hello, world 4").unwrap();
+        });
+
+        let mut lines = chaser
+            .lines()
+            .unwrap()
+            .idle_timeout(Duration::from_millis(500));
+        let seen: Vec<(String, Line, Pos)> = lines.by_ref().map(|rec| rec.unwrap()).collect();
+        let state = lines.state().unwrap();
+        assert_eq!(state.rotations, 1);
+        assert_eq!(state.line, Line(1));
+        assert_eq!(state.lines_read, 3);
+        assert!(lines.next().is_none());
+        writer.join().unwrap();
+
+        assert_eq!(
+            seen,
+            vec![
+                ("Hello, world 2".to_string(), Line(1), Pos(15)),
+                ("Hello, world 3".to_string(), Line(2), Pos(30)),
+                ("Hello, world 4".to_string(), Line(0), Pos(0)),
+            ]
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn lines_with_handle_test() {
+        let temp_dir = TempDir::new("chase-test-iter-handle").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+
+        let handle = ControlHandle::new();
+        let stopper = handle.clone();
+        let stopping = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            stopper.stop();
+        });
+
+        // Without an idle timeout, only stopping the handle ends iteration
+        let seen: Vec<(String, Line, Pos)> = chaser
+            .lines_with_handle(&handle)
+            .unwrap()
+            .map(|rec| rec.unwrap())
+            .collect();
+        stopping.join().unwrap();
+        assert_eq!(seen, vec![("Hello, world 1".to_string(), Line(0), Pos(0))]);
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+}
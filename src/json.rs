@@ -0,0 +1,258 @@
+//! Holds logic for chasing files made up of JSON lines, parsing each line as it is read.
+
+use data::*;
+use control::*;
+use errors::ChaseError;
+use sync::Deliver;
+
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use std::marker::PhantomData;
+
+/// A line of a JSON lines file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum JsonLine<T> {
+    /// The line, parsed
+    Parsed(T),
+    /// The line as it was read, because it couldn't be parsed (only delivered with
+    /// `UnparseablePolicy::DeliverRaw`)
+    Raw(String),
+}
+
+pub(crate) type JsonData<T> = (JsonLine<T>, Line, Pos);
+
+/// What to do with lines that can't be parsed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnparseablePolicy {
+    /// Skip the line, reporting it on the ControlHandle chasing is done with as a
+    /// `ChaseError::Unparseable`. Skipped lines don't get a sequence number.
+    Skip,
+    /// Deliver the line as it was read, as `JsonLine::Raw`
+    DeliverRaw,
+    /// Stop chasing with a `ChaseError::Unparseable`
+    Error,
+}
+
+impl Chaser {
+    /// Start chasing a file synchronously, parsing every line read as JSON, either into a
+    /// `serde_json::Value` or into a type of your own.
+    ///
+    /// The provided callback function will be invoked with every line parsed, unless it
+    /// can't be parsed and the `on_unparseable` policy says to skip it.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # extern crate serde_json;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # use serde_json::Value;
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-json-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let mut chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// writeln!(file_write, r#"{{"level": "info", "msg": "Hello, world 1"}}"#).unwrap();
+    /// writeln!(file_write, "Hello, world 2").unwrap();
+    ///
+    /// let mut seen = Vec::new();
+    /// chaser.run_json(UnparseablePolicy::DeliverRaw, |json: JsonLine<Value>, num, _| {
+    ///     seen.push((json, num));
+    ///     if seen.len() < 2 {
+    ///         Ok(Control::Continue)
+    ///     } else {
+    ///         Ok(Control::Stop)
+    ///     }
+    /// }).unwrap();
+    ///
+    /// match seen[0] {
+    ///     (JsonLine::Parsed(ref value), Line(0)) => assert_eq!(value["msg"], "Hello, world 1"),
+    ///     ref other => panic!("Unexpected line {:?}", other),
+    /// }
+    /// assert_eq!(seen[1], (JsonLine::Raw("Hello, world 2".to_string()), Line(1)));
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn run_json<T, F>(
+        &mut self,
+        on_unparseable: UnparseablePolicy,
+        f: F,
+    ) -> Result<(), ChaseError>
+    where
+        T: DeserializeOwned,
+        F: FnMut(JsonLine<T>, Line, Pos) -> Result<Control, ChaseError>,
+    {
+        let deliver = EachJson {
+            on_unparseable,
+            f,
+            parsing: PhantomData,
+        };
+        self.run_controlled(&ControlHandle::new(), deliver)
+            .map(|_| ())
+            .map_err(|failure| failure.error)
+    }
+}
+
+// Hands every line read, parsed, to the wrapped function
+struct EachJson<T, F> {
+    on_unparseable: UnparseablePolicy,
+    f: F,
+    parsing: PhantomData<fn() -> T>,
+}
+
+impl<T, F> Deliver for EachJson<T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(JsonLine<T>, Line, Pos) -> Result<Control, ChaseError>,
+{
+    fn line(&mut self, running: &Chasing, _: usize) -> Result<Control, ChaseError> {
+        let mut parsed = match parse_json(running, self.on_unparseable)? {
+            Some(parsed) => Some(parsed),
+            None => return Ok(Control::Continue),
+        };
+        let on_unparseable = self.on_unparseable;
+        let f = &mut self.f;
+        running.call_back(|| {
+            // Parsing again is only needed when the callback is being retried
+            let json = match parsed.take() {
+                Some(json) => json,
+                None => match parse_json(running, on_unparseable)? {
+                    Some(json) => json,
+                    None => return Ok(Control::Continue),
+                },
+            };
+            f(json, running.line, running.pos)
+        })
+    }
+}
+
+/// Parses the line that was just read, giving back None if it couldn't be parsed and
+/// should be skipped.
+pub(crate) fn parse_json<T>(
+    running: &Chasing,
+    on_unparseable: UnparseablePolicy,
+) -> Result<Option<JsonLine<T>>, ChaseError>
+where
+    T: DeserializeOwned,
+{
+    let text = running.text();
+    match serde_json::from_str(text) {
+        Ok(value) => Ok(Some(JsonLine::Parsed(value))),
+        Err(e) => {
+            let error = ChaseError::Unparseable {
+                line: running.line,
+                pos: running.pos,
+                source: Box::new(e),
+            };
+            match on_unparseable {
+                UnparseablePolicy::Skip => {
+                    running.undelivered.set(true);
+                    running.handle.report_skipped(error);
+                    Ok(None)
+                }
+                UnparseablePolicy::DeliverRaw => Ok(Some(JsonLine::Raw(text.to_string()))),
+                UnparseablePolicy::Error => Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::ChaseError;
+    use json::*;
+    use tempdir::*;
+    use std::io::Write;
+
+    use std::fs::OpenOptions;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Entry {
+        level: String,
+        msg: String,
+    }
+
+    #[test]
+    fn run_json_test() {
+        let temp_dir = TempDir::new("chase-test-json").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, r#"{{"level": "info", "msg": "Hello, world 1"}}"#).unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        writeln!(file_write, r#"{{"level": "warn", "msg": "Hello, world 3"}}"#).unwrap();
+
+        let mut seen = Vec::new();
+        chaser
+            .run_json(UnparseablePolicy::Skip, |json: JsonLine<Entry>, num, _| {
+                seen.push((json, num));
+                Ok(if seen.len() < 2 {
+                    Control::Continue
+                } else {
+                    Control::Stop
+                })
+            })
+            .unwrap();
+        assert_eq!(
+            seen,
+            vec![
+                (
+                    JsonLine::Parsed(Entry {
+                        level: "info".to_string(),
+                        msg: "Hello, world 1".to_string(),
+                    }),
+                    Line(0),
+                ),
+                (
+                    JsonLine::Parsed(Entry {
+                        level: "warn".to_string(),
+                        msg: "Hello, world 3".to_string(),
+                    }),
+                    Line(2),
+                ),
+            ]
+        );
+
+        // The line that was skipped doesn't count towards the sequence number
+        let deliver = EachJson {
+            on_unparseable: UnparseablePolicy::Skip,
+            f: |_: JsonLine<Entry>, num, _| {
+                Ok(if num < Line(2) {
+                    Control::Continue
+                } else {
+                    Control::Stop
+                })
+            },
+            parsing: PhantomData,
+        };
+        let state = chaser.run_controlled(&ControlHandle::new(), deliver).unwrap();
+        assert_eq!((state.lines_read, state.seq), (3, 2));
+
+        let result = chaser.run_json(UnparseablePolicy::Error, |_: JsonLine<Entry>, _, _| {
+            Ok(Control::Continue)
+        });
+        match result {
+            Err(ChaseError::Unparseable { line, pos, .. }) => {
+                assert_eq!((line, pos), (Line(1), Pos(43)))
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+}
@@ -68,6 +68,11 @@
 //! elements that it tries to send you afterwards will bubble up to you as the Err
 //! result of joining the thread, which is the same as how channels normally act.
 //!
+//! Since that only happens when there is something to send, use the `ControlHandle`
+//! that is handed back alongside the receiver to stop a chaser that is sitting idle;
+//! the thread will then exit promptly. The same handle can also be used to seek,
+//! rewind, skip to the end, or pause.
+//!
 //! ```
 //! # extern crate chase;
 //! # extern crate tempdir;
@@ -92,7 +97,7 @@
 //!
 //! let mut seen = String::new();
 //!
-//! let (receiver, _) = chaser.run_channel().unwrap();
+//! let (receiver, stop_handle, join_handle) = chaser.run_channel().unwrap();
 //!
 //! seen.push_str(&receiver.recv().unwrap().0);
 //! seen.push_str(&receiver.recv().unwrap().0);
@@ -103,6 +108,9 @@
 //! seen.push_str(&receiver.recv().unwrap().0);
 //! assert_eq!(seen.as_str(), "Hello, world 1Hello, world 2Hello, world 3");
 //!
+//! stop_handle.stop();
+//! assert!(join_handle.join().unwrap().is_ok());
+//!
 //! drop(receiver);
 //! drop(file_write);
 //! temp_dir.close().unwrap();
@@ -112,11 +120,37 @@
 #[cfg(feature = "stream")]
 extern crate futures;
 
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(feature = "timestamps")]
+extern crate chrono;
+#[cfg(feature = "redact")]
+extern crate hmac_sha256;
+#[cfg(feature = "regex")]
+extern crate regex;
+
 #[cfg(test)]
 extern crate tempdir;
 
 mod data;
 mod sync;
+mod batch;
+#[cfg(feature = "collapse")]
+mod collapse;
+mod iter;
+#[cfg(feature = "filter")]
+mod filter;
+#[cfg(feature = "grok")]
+mod grok;
+mod limit;
+#[cfg(feature = "redact")]
+mod redact;
+#[cfg(feature = "timestamps")]
+mod timestamp;
+mod logfmt;
+#[cfg(feature = "with-serde")]
+mod json;
 mod async;
 mod errors;
 mod control;
@@ -125,9 +159,47 @@ mod control;
 #[cfg_attr(feature = "with-serde", macro_use)]
 extern crate serde_derive;
 
-pub use data::{Chaser, Line, Pos, DEFAULT_NOT_ROTATED_WAIT_MILLIS,
+#[cfg(feature = "with-serde")]
+extern crate serde;
+
+#[cfg(all(feature = "with-serde", not(test)))]
+extern crate serde_json;
+
+#[cfg(all(feature = "with-serde", test))]
+#[macro_use]
+extern crate serde_json;
+
+pub use data::{ChaseState, Chaser, ErrorPolicy, FileId, Line, OverflowPolicy, Pos, RateLimit,
+               RateLimitPolicy, Record, Sampling, DEFAULT_NOT_ROTATED_WAIT_MILLIS,
                DEFAULT_ROTATION_CHECK_WAIT_MILLIS};
 
-pub use errors::ChaseError;
+#[cfg(feature = "filter")]
+pub use data::Filter;
+
+#[cfg(feature = "grok")]
+pub use data::Grok;
 
-pub use control::Control;
+#[cfg(feature = "redact")]
+pub use data::{Detector, Redaction, Replacement};
+
+pub use errors::{ChaseError, ChaseFailure};
+
+pub use control::{Control, ControlHandle, MAX_REPORTED_SKIPS};
+
+pub use async::ChaseThread;
+
+pub use iter::Lines;
+
+#[cfg(feature = "collapse")]
+pub use collapse::Repeated;
+
+#[cfg(feature = "grok")]
+pub use grok::GROK_PATTERNS;
+
+pub use logfmt::Logfmt;
+
+#[cfg(feature = "timestamps")]
+pub use timestamp::TimestampFormat;
+
+#[cfg(feature = "with-serde")]
+pub use json::{JsonLine, UnparseablePolicy};
@@ -0,0 +1,277 @@
+//! Holds logic for rate limiting and sampling lines as they are read, before they are
+//! delivered
+
+use data::*;
+use control::ControlHandle;
+
+use std::time::{Duration, Instant};
+
+/// What a Limiter decided to do with a line
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Admission {
+    Admit,
+    Drop,
+    /// We were stopped while waiting for the line to be let through
+    Stopped,
+}
+
+/// A compiled RateLimit
+#[derive(Debug)]
+pub(crate) struct Limiter {
+    lines: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    policy: RateLimitPolicy,
+}
+
+impl RateLimit {
+    pub(crate) fn limiter(&self) -> Limiter {
+        let now = Instant::now();
+        Limiter {
+            lines: self.lines_per_second.map(|rate| TokenBucket::new(rate, now)),
+            bytes: self.bytes_per_second.map(|rate| TokenBucket::new(rate, now)),
+            policy: self.policy,
+        }
+    }
+}
+
+impl Limiter {
+    /// Decides whether a line of the given length can be delivered, waiting until it can
+    /// if we're to block
+    pub(crate) fn admit(&mut self, bytes: usize, handle: &ControlHandle) -> Admission {
+        loop {
+            let now = Instant::now();
+            let wait = self.lines
+                .as_mut()
+                .map(|bucket| bucket.wait_for(1.0, now))
+                .into_iter()
+                .chain(self.bytes.as_mut().map(|b| b.wait_for(bytes as f64, now)))
+                .max()
+                .unwrap_or_default();
+            if wait == Duration::from_secs(0) {
+                if let Some(ref mut bucket) = self.lines {
+                    bucket.take(1.0);
+                }
+                if let Some(ref mut bucket) = self.bytes {
+                    bucket.take(bytes as f64);
+                }
+                return Admission::Admit;
+            }
+            match self.policy {
+                RateLimitPolicy::Drop => return Admission::Drop,
+                RateLimitPolicy::Block => {
+                    if handle.wait(wait) {
+                        return Admission::Stopped;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Holds up to a second's worth of tokens, and starts out full
+#[derive(Debug)]
+struct TokenBucket {
+    per_second: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_second: u64, now: Instant) -> TokenBucket {
+        let per_second = per_second.max(1) as f64;
+        TokenBucket {
+            per_second,
+            tokens: per_second,
+            refilled_at: now,
+        }
+    }
+
+    // How long until there are enough tokens for the given cost. Costs bigger than the
+    // bucket only need it to be full.
+    fn wait_for(&mut self, cost: f64, now: Instant) -> Duration {
+        let elapsed = now.duration_since(self.refilled_at);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.per_second).min(self.per_second);
+        self.refilled_at = now;
+        let missing = cost.min(self.per_second) - self.tokens;
+        if missing > 0.0 {
+            Duration::from_secs_f64(missing / self.per_second)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    fn take(&mut self, cost: f64) {
+        self.tokens -= cost.min(self.per_second);
+    }
+}
+
+/// Keeps track of which lines a Sampling lets through
+#[derive(Debug)]
+pub(crate) struct Sampler {
+    sampling: Sampling,
+    seen: u64,
+}
+
+impl Sampling {
+    pub(crate) fn sampler(&self) -> Sampler {
+        Sampler {
+            sampling: *self,
+            seen: 0,
+        }
+    }
+}
+
+impl Sampler {
+    /// Whether the given line should be delivered
+    // `is_multiple_of` would need Rust 1.87
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    pub(crate) fn keeps(&mut self, text: &str) -> bool {
+        match self.sampling {
+            Sampling::OneIn(n) => {
+                let keep = self.seen % n.max(1) == 0;
+                self.seen += 1;
+                keep
+            }
+            Sampling::Hash(n) => fnv1a(text.as_bytes()) % n.max(1) == 0,
+        }
+    }
+}
+
+// FNV-1a, which unlike the standard library's hasher is guaranteed to give the same
+// hashes from one release to the next
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl<'a> Chasing<'a> {
+    /// Decides whether the line that was just read gets delivered, going by the chaser's
+    /// Sampling and then its RateLimit. Lines that are sampled out or dropped are counted
+    /// on the ControlHandle.
+    pub(crate) fn admit(&mut self, bytes_read: usize) -> Admission {
+        if let Some(ref mut sampler) = self.sampler {
+            if !sampler.keeps(self.buffer.trim_end_matches('\n')) {
+                self.handle.count_sampled_out_lines(1);
+                return Admission::Drop;
+            }
+        }
+        let admission = match self.limiter {
+            Some(ref mut limiter) => limiter.admit(bytes_read, &self.handle),
+            None => Admission::Admit,
+        };
+        if admission == Admission::Drop {
+            self.handle.count_rate_limited_lines(1);
+        }
+        admission
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use limit::*;
+    use control::*;
+    use tempdir::*;
+    use std::io::Write;
+    use std::thread;
+
+    use std::fs::OpenOptions;
+
+    #[test]
+    fn limiter_test() {
+        let handle = ControlHandle::new();
+        let mut dropping = RateLimit {
+            lines_per_second: Some(3),
+            bytes_per_second: None,
+            policy: RateLimitPolicy::Drop,
+        }.limiter();
+        let admitted: Vec<_> = (0..5).map(|_| dropping.admit(10, &handle)).collect();
+        assert_eq!(
+            admitted,
+            vec![
+                Admission::Admit,
+                Admission::Admit,
+                Admission::Admit,
+                Admission::Drop,
+                Admission::Drop,
+            ]
+        );
+
+        // 100 bytes a second, so after the first line the next one has to wait for half a
+        // second
+        let mut blocking = RateLimit {
+            lines_per_second: None,
+            bytes_per_second: Some(100),
+            policy: RateLimitPolicy::Block,
+        }.limiter();
+        let started = Instant::now();
+        assert_eq!(blocking.admit(100, &handle), Admission::Admit);
+        assert_eq!(blocking.admit(50, &handle), Admission::Admit);
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        // Lines bigger than the bucket still get through once it is full
+        assert_eq!(blocking.admit(1000, &handle), Admission::Admit);
+
+        handle.stop();
+        assert_eq!(blocking.admit(100, &handle), Admission::Stopped);
+    }
+
+    #[test]
+    fn sampler_test() {
+        let mut one_in = Sampling::OneIn(3).sampler();
+        let kept: Vec<_> = (0..7).map(|_| one_in.keeps("line")).collect();
+        assert_eq!(kept, vec![true, false, false, true, false, false, true]);
+
+        let lines: Vec<_> = (0..1000).map(|n| format!("Hello, world {}", n)).collect();
+        let mut hashed = Sampling::Hash(10).sampler();
+        let kept: Vec<_> = lines.iter().filter(|line| hashed.keeps(line)).collect();
+        assert!(kept.len() > 50 && kept.len() < 150, "kept {}", kept.len());
+        // The same lines are kept every time
+        let mut again = Sampling::Hash(10).sampler();
+        assert!(kept.iter().all(|line| again.keeps(line)));
+    }
+
+    #[test]
+    fn run_sampled_test() {
+        let temp_dir = TempDir::new("chase-test-limit").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.sampling = Some(Sampling::OneIn(2));
+        chaser.rate_limit = Some(RateLimit {
+            lines_per_second: Some(1),
+            bytes_per_second: None,
+            policy: RateLimitPolicy::Drop,
+        });
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        for n in 1..7 {
+            writeln!(file_write, "Hello, world {}", n).unwrap();
+        }
+
+        let handle = ControlHandle::new();
+        let stopper = handle.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            stopper.stop();
+        });
+        let mut seen = Vec::new();
+        let state = chaser
+            .run_with_handle(&handle, |line, _, _| {
+                seen.push(line.to_string());
+                Ok(Control::Continue)
+            })
+            .unwrap();
+
+        // Every other line is sampled out, and only one of the rest fits in a second
+        assert_eq!(seen, vec!["Hello, world 1".to_string()]);
+        assert_eq!(handle.sampled_out_lines(), 3);
+        assert_eq!(handle.rate_limited_lines(), 2);
+        assert_eq!((state.lines_read, state.seq), (6, 1));
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+//! Holds a parser for logfmt (`level=info msg="x y" dur=3ms`) lines, and logic for
+//! chasing files made up of them.
+
+use data::*;
+use control::*;
+use errors::ChaseError;
+use sync::Deliver;
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[cfg(feature = "with-serde")]
+use serde::{Serialize, Serializer};
+#[cfg(feature = "with-serde")]
+use serde::ser::SerializeMap;
+
+/// The key/value pairs of a logfmt line, in the order they appeared in.
+///
+/// Keys that appear without a value (e.g. `debug` in `level=info debug`) have None as
+/// their value, whereas keys with an empty value (`msg=` or `msg=""`) have an empty
+/// string.
+///
+/// A key can appear more than once, in which case all of its pairs are kept, but the
+/// first one wins wherever there can only be one, as with `get` and serializing.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Logfmt {
+    pub pairs: Vec<(String, Option<String>)>,
+}
+
+impl Logfmt {
+    /// Parses a logfmt line. This never fails: anything that isn't a key/value pair is
+    /// taken to be a key without a value.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # use chase::*;
+    /// # fn main () {
+    /// let parsed = Logfmt::parse(r#"level=info msg="Hello, \"world\"" debug dur=3ms"#);
+    /// assert_eq!(parsed.get("msg"), Some(r#"Hello, "world""#));
+    /// assert_eq!(parsed.get("debug"), None);
+    /// assert_eq!(parsed.pairs[2], ("debug".to_string(), None));
+    /// assert_eq!(parsed.to_string(), r#"level=info msg="Hello, \"world\"" debug dur=3ms"#);
+    /// # }
+    /// ```
+    pub fn parse(line: &str) -> Logfmt {
+        let mut chars = line.chars().peekable();
+        let mut pairs = Vec::new();
+        loop {
+            skip_whitespace(&mut chars);
+            if chars.peek().is_none() {
+                break;
+            }
+            let key = if chars.peek() == Some(&'"') {
+                chars.next();
+                quoted(&mut chars)
+            } else {
+                unquoted(&mut chars, true)
+            };
+            let value = if chars.peek() == Some(&'=') {
+                chars.next();
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    Some(quoted(&mut chars))
+                } else {
+                    Some(unquoted(&mut chars, false))
+                }
+            } else {
+                None
+            };
+            pairs.push((key, value));
+        }
+        Logfmt { pairs }
+    }
+
+    /// The value of the first pair with the given key, if it has one
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.as_deref())
+    }
+}
+
+/// Writes the pairs back out as logfmt, quoting where needed
+impl fmt::Display for Logfmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (key, value)) in self.pairs.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write_escaped(f, key)?;
+            if let Some(value) = value {
+                write!(f, "=")?;
+                write_escaped(f, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serializes as a map, keeping the order of the pairs, with keys that have no value
+/// mapped to `true`, and only the first pair of keys that appear more than once
+#[cfg(feature = "with-serde")]
+impl Serialize for Logfmt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let firsts: Vec<_> = self.pairs
+            .iter()
+            .enumerate()
+            .filter(|&(i, (key, _))| !self.pairs[..i].iter().any(|(k, _)| k == key))
+            .map(|(_, pair)| pair)
+            .collect();
+        let mut map = serializer.serialize_map(Some(firsts.len()))?;
+        for (key, value) in firsts {
+            match value {
+                Some(value) => map.serialize_entry(key, value)?,
+                None => map.serialize_entry(key, &true)?,
+            }
+        }
+        map.end()
+    }
+}
+
+impl Chaser {
+    /// Start chasing a file synchronously, parsing every line read as logfmt.
+    ///
+    /// The provided callback function will be invoked with the key/value pairs of every
+    /// line read.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-logfmt-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let mut chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// writeln!(file_write, r#"level=info msg="Hello, world 1" dur=3ms"#).unwrap();
+    ///
+    /// let mut seen = Vec::new();
+    /// chaser.run_logfmt(|pairs, num, _| {
+    ///     seen.push((pairs.get("msg").unwrap().to_string(), num));
+    ///     Ok(Control::Stop)
+    /// }).unwrap();
+    ///
+    /// assert_eq!(seen, vec![("Hello, world 1".to_string(), Line(0))]);
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn run_logfmt<F>(&mut self, f: F) -> Result<(), ChaseError>
+    where
+        F: FnMut(Logfmt, Line, Pos) -> Result<Control, ChaseError>,
+    {
+        self.run_controlled(&ControlHandle::new(), EachLogfmt(f))
+            .map(|_| ())
+            .map_err(|failure| failure.error)
+    }
+}
+
+// Hands every line read, parsed as logfmt, to the wrapped function
+struct EachLogfmt<F>(F);
+
+impl<F> Deliver for EachLogfmt<F>
+where
+    F: FnMut(Logfmt, Line, Pos) -> Result<Control, ChaseError>,
+{
+    fn line(&mut self, running: &Chasing, _: usize) -> Result<Control, ChaseError> {
+        let f = &mut self.0;
+        running.call_back(|| f(Logfmt::parse(running.text()), running.line, running.pos))
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+}
+
+// Reads up to the next whitespace (or, for keys, '=')
+fn unquoted(chars: &mut Peekable<Chars>, is_key: bool) -> String {
+    let mut read = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || (is_key && c == '=') {
+            break;
+        }
+        read.push(c);
+        chars.next();
+    }
+    read
+}
+
+// Reads up to the closing quote, unescaping on the way. The opening quote has already
+// been read; a missing closing quote means the rest of the line is taken.
+fn quoted(chars: &mut Peekable<Chars>) -> String {
+    let mut read = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => read.push('\n'),
+                Some('t') => read.push('\t'),
+                Some('r') => read.push('\r'),
+                Some(other) => read.push(other),
+                None => read.push('\\'),
+            },
+            other => read.push(other),
+        }
+    }
+    read
+}
+
+fn write_escaped(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    let needs_quotes =
+        s.is_empty() || s.chars().any(|c| c.is_whitespace() || c == '"' || c == '=');
+    if !needs_quotes {
+        return write!(f, "{}", s);
+    }
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            other => write!(f, "{}", other)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use logfmt::*;
+
+    fn pairs(pairs: &[(&str, Option<&str>)]) -> Logfmt {
+        Logfmt {
+            pairs: pairs
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v.map(|v| v.to_string())))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(Logfmt::parse(""), Logfmt::default());
+        assert_eq!(
+            Logfmt::parse(r#"level=info msg="x y" dur=3ms"#),
+            pairs(&[
+                ("level", Some("info")),
+                ("msg", Some("x y")),
+                ("dur", Some("3ms")),
+            ])
+        );
+        assert_eq!(
+            Logfmt::parse("  debug  empty= quoted=\"\" url=http://a/?b=c "),
+            pairs(&[
+                ("debug", None),
+                ("empty", Some("")),
+                ("quoted", Some("")),
+                ("url", Some("http://a/?b=c")),
+            ])
+        );
+        assert_eq!(
+            Logfmt::parse(r#"err="a \"b\"\n\\c" "odd key"=1 open="never closed"#),
+            pairs(&[
+                ("err", Some("a \"b\"\n\\c")),
+                ("odd key", Some("1")),
+                ("open", Some("never closed")),
+            ])
+        );
+    }
+
+    #[test]
+    fn display_test() {
+        let line = r#"level=info msg="x \"y\"" debug empty="" "odd key"=1"#;
+        let parsed = Logfmt::parse(line);
+        assert_eq!(parsed.to_string(), line);
+        assert_eq!(Logfmt::parse(&parsed.to_string()), parsed);
+    }
+
+    #[cfg(feature = "with-serde")]
+    #[test]
+    fn serialize_test() {
+        use serde_json;
+
+        let parsed = Logfmt::parse(r#"level=info msg="x y" debug b=2 a=1"#);
+        assert_eq!(
+            serde_json::to_string(&parsed).unwrap(),
+            r#"{"level":"info","msg":"x y","debug":true,"b":"2","a":"1"}"#
+        );
+
+        let repeated = Logfmt::parse("a=1 b a=2 b=3");
+        assert_eq!(repeated.get("a"), Some("1"));
+        assert_eq!(
+            serde_json::to_string(&repeated).unwrap(),
+            r#"{"a":"1","b":true}"#
+        );
+    }
+}
@@ -1,20 +1,85 @@
 extern crate chase;
+extern crate chrono;
 #[macro_use]
 extern crate clap;
+extern crate ctrlc;
+extern crate humantime;
+#[cfg(unix)]
+extern crate libc;
+extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+#[cfg(test)]
+extern crate tempdir;
+
+mod cli;
 
-use clap::{App, Arg};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone};
+use clap::{App, Arg, ArgMatches};
+use regex::Regex;
 
 use std::error::Error;
+#[cfg(unix)]
+use std::io;
+use std::path::PathBuf;
 use std::process::exit;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 use chase::*;
 
-const FILE_KEY: &'static str = "f";
-const LINE_KEY: &'static str = "l";
+use cli::checkpoint::Checkpointer;
+use cli::commands::Commands;
+use cli::output::{parse_output, pretty, render, Output};
+
+const FILE_KEY: &str = "f";
+const LINE_KEY: &str = "l";
+const GREP_KEY: &str = "grep";
+const EXCLUDE_KEY: &str = "exclude";
+const IGNORE_CASE_KEY: &str = "ignore-case";
+const LOGFMT_KEY: &str = "logfmt";
+const SINCE_KEY: &str = "since";
+const GROK_KEY: &str = "grok";
+const COLLAPSE_KEY: &str = "collapse";
+const REDACT_KEY: &str = "redact";
+const REDACT_PATTERN_KEY: &str = "redact-pattern";
+const REDACT_KEY_KEY: &str = "redact-key";
+const PREFIX_KEY: &str = "prefix";
+const INITIAL_NO_FILE_WAIT_KEY: &str = "initial-no-file-wait";
+const INITIAL_NO_FILE_ATTEMPTS_KEY: &str = "initial-no-file-attempts";
+const ROTATION_CHECK_WAIT_KEY: &str = "rotation-check-wait";
+const ROTATION_CHECK_ATTEMPTS_KEY: &str = "rotation-check-attempts";
+const NOT_ROTATED_WAIT_KEY: &str = "not-rotated-wait";
+const NO_FOLLOW_KEY: &str = "no-follow";
+const OUTPUT_KEY: &str = "output";
+const STATE_FILE_KEY: &str = "state-file";
+const UNTIL_KEY: &str = "until";
+const MAX_LINES_KEY: &str = "max-lines";
+const IDLE_TIMEOUT_KEY: &str = "idle-timeout";
+const PID_KEY: &str = "pid";
+const EXEC_KEY: &str = "exec";
+const EXEC_LIMIT_KEY: &str = "exec-limit";
+const PIPE_KEY: &str = "pipe";
+const DEBOUNCE_KEY: &str = "debounce";
+
+const REDACTION_MASK: &str = "[REDACTED]";
+
+// How often the process given with --pid is checked on
+const PID_CHECK_INTERVAL_MILLIS: u64 = 1000;
+
+// How long a run of repeated lines is held on to before it gets printed anyway
+const COLLAPSE_FLUSH_AFTER_MILLIS: u64 = 1000;
+
+// How many lines may be waiting to be printed before the threads chasing files are held up
+const PRINT_QUEUE_CAPACITY: usize = 1024;
 
 fn main() {
     match inner_main() {
-        Ok(_) => exit(0),
+        Ok(code) => exit(code),
         Err(e) => {
             println!("{}", e);
             exit(1)
@@ -22,17 +87,34 @@ fn main() {
     }
 }
 
-fn inner_main() -> Result<(), Box<Error>> {
+fn inner_main() -> Result<i32, Box<dyn Error>> {
     let app = App::new("chase")
         .version(crate_version!())
         .author("Lloyd (github.com/lloydmeta)")
         .about("Chases a file through thick and thin.")
+        .after_help(
+            "EXIT STATUS:\n    \
+             0    Chasing ended by itself or on SIGINT/SIGTERM\n    \
+             1    Something went wrong\n    \
+             2    --max-lines lines were printed\n    \
+             3    Nothing came along for --idle-timeout\n    \
+             4    The process given with --pid exited\n    \
+             5    A line matched --until",
+        )
         .arg(
             Arg::with_name(FILE_KEY)
                 .takes_value(true)
-                .number_of_values(1)
+                .multiple(true)
                 .required(true)
-                .help("The file you want to chase"),
+                .help("The file(s) you want to chase"),
+        )
+        .arg(
+            Arg::with_name(PREFIX_KEY)
+                .long("prefix")
+                .help(
+                    "When chasing several files, puts the file's name in front of every line \
+                     rather than printing a header whenever output switches between files",
+                ),
         )
         .arg(
             Arg::with_name(LINE_KEY)
@@ -44,23 +126,820 @@ fn inner_main() -> Result<(), Box<Error>> {
                 .required(false)
                 .default_value("0")
                 .help("The line you want to start chasing your file from"),
+        )
+        .arg(
+            Arg::with_name(STATE_FILE_KEY)
+                .long("state-file")
+                .takes_value(true)
+                .number_of_values(1)
+                .help(
+                    "Saves how far each file has been chased to this file every second and \
+                     on SIGINT or SIGTERM, and picks up from there on the next run (unless \
+                     the file has been replaced since, in which case --line or --since is \
+                     used)",
+                ),
+        )
+        .arg(
+            Arg::with_name(UNTIL_KEY)
+                .long("until")
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|s| Regex::new(&s).map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("Exits once a line matching this regex has been printed"),
+        )
+        .arg(
+            Arg::with_name(MAX_LINES_KEY)
+                .long("max-lines")
+                .short("n")
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(0) => Err("must be at least 1".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(format!("{}", e)),
+                })
+                .help("Exits once this many lines have been printed"),
+        )
+        .arg(
+            Arg::with_name(IDLE_TIMEOUT_KEY)
+                .long("idle-timeout")
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|s| parse_duration(&s).map(|_| ()))
+                .help("Exits if nothing new comes along for this long, e.g. 30s or 5m"),
+        )
+        .arg(
+            Arg::with_name(PID_KEY)
+                .long("pid")
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|s| s.parse::<u32>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help("Exits once the process with this id does, like tail --pid"),
+        )
+        .arg(
+            Arg::with_name(EXEC_KEY)
+                .long("exec")
+                .takes_value(true)
+                .number_of_values(1)
+                .help(
+                    "Runs this with the shell for every line printed, with the line, its line \
+                     number and the file's path as $1, $2 and $3, and in CHASE_LINE, \
+                     CHASE_LINE_NUMBER and CHASE_PATH",
+                ),
+        )
+        .arg(
+            Arg::with_name(EXEC_LIMIT_KEY)
+                .long("exec-limit")
+                .takes_value(true)
+                .number_of_values(1)
+                .requires(EXEC_KEY)
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(0) => Err("must be at least 1".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(format!("{}", e)),
+                })
+                .help(
+                    "How many --exec commands can be running at once. Past this, we wait for \
+                     one to finish before going on. [default: 1]",
+                ),
+        )
+        .arg(
+            Arg::with_name(PIPE_KEY)
+                .long("pipe")
+                .takes_value(true)
+                .number_of_values(1)
+                .help(
+                    "Starts this with the shell and writes every line printed to its stdin, \
+                     starting it again whenever it exits (at most once a second; lines that \
+                     come along before then are dropped)",
+                ),
+        )
+        .arg(
+            Arg::with_name(DEBOUNCE_KEY)
+                .long("debounce")
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|s| parse_duration(&s).map(|_| ()))
+                .help(
+                    "Hands lines to --exec and --pipe at most once per this long, e.g. 10s, \
+                     leaving out the lines in between",
+                ),
+        )
+        .arg(
+            Arg::with_name(NO_FOLLOW_KEY)
+                .long("no-follow")
+                .help("Exits once the end of the file is reached, rather than waiting for more"),
+        )
+        .arg(
+            Arg::with_name(INITIAL_NO_FILE_WAIT_KEY)
+                .long("initial-no-file-wait")
+                .takes_value(true)
+                .number_of_values(1)
+                .env("CHASE_INITIAL_NO_FILE_WAIT")
+                .validator(|s| parse_duration(&s).map(|_| ()))
+                .help(
+                    "How long to wait before looking for the file again if it isn't there at \
+                     first, e.g. 250ms or 5s [default: 100ms]",
+                ),
+        )
+        .arg(
+            Arg::with_name(INITIAL_NO_FILE_ATTEMPTS_KEY)
+                .long("initial-no-file-attempts")
+                .takes_value(true)
+                .number_of_values(1)
+                .env("CHASE_INITIAL_NO_FILE_ATTEMPTS")
+                .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help(
+                    "How many times to look for the file before giving up, if it isn't there \
+                     at first [default: no limit]",
+                ),
+        )
+        .arg(
+            Arg::with_name(ROTATION_CHECK_WAIT_KEY)
+                .long("rotation-check-wait")
+                .takes_value(true)
+                .number_of_values(1)
+                .env("CHASE_ROTATION_CHECK_WAIT")
+                .validator(|s| parse_duration(&s).map(|_| ()))
+                .help(
+                    "How long to wait before looking for the file again if it goes missing \
+                     while checking whether it was rotated [default: 100ms]",
+                ),
+        )
+        .arg(
+            Arg::with_name(ROTATION_CHECK_ATTEMPTS_KEY)
+                .long("rotation-check-attempts")
+                .takes_value(true)
+                .number_of_values(1)
+                .env("CHASE_ROTATION_CHECK_ATTEMPTS")
+                .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| format!("{}", e)))
+                .help(
+                    "How many times to look for the file while checking whether it was \
+                     rotated before giving up [default: no limit]",
+                ),
+        )
+        .arg(
+            Arg::with_name(NOT_ROTATED_WAIT_KEY)
+                .long("not-rotated-wait")
+                .takes_value(true)
+                .number_of_values(1)
+                .env("CHASE_NOT_ROTATED_WAIT")
+                .validator(|s| parse_duration(&s).map(|_| ()))
+                .help(
+                    "How long to wait before reading again after getting to the end of the \
+                     file [default: 50ms]",
+                ),
+        )
+        .arg(
+            Arg::with_name(SINCE_KEY)
+                .long("since")
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|s| parse_since(&s).map(|_| ()))
+                .help(
+                    "Start chasing from the first line timestamped at or after this, e.g. \
+                     14:05 (today), \"2018-02-03 14:05\" or 2018-02-03T14:05:00+01:00. Times \
+                     without an offset are in local time, but line timestamps without one are \
+                     taken to be in UTC.",
+                ),
+        )
+        .arg(
+            Arg::with_name(GREP_KEY)
+                .long("grep")
+                .short("g")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only print lines matching this regex (or any of them, if repeated)"),
+        )
+        .arg(
+            Arg::with_name(EXCLUDE_KEY)
+                .long("exclude")
+                .short("x")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Don't print lines matching this regex (or any of them, if repeated)"),
+        )
+        .arg(
+            Arg::with_name(IGNORE_CASE_KEY)
+                .long("ignore-case")
+                .short("i")
+                .help("Makes --grep and --exclude ignore case"),
+        )
+        .arg(
+            Arg::with_name(REDACT_KEY)
+                .long("redact")
+                .help(
+                    "Redacts JWTs, AWS keys, email addresses, credit card numbers and IP \
+                     addresses from lines",
+                ),
+        )
+        .arg(
+            Arg::with_name(REDACT_PATTERN_KEY)
+                .long("redact-pattern")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Redacts whatever matches this regex too (or, if it has a group named \
+                     redact, whatever that group matches)",
+                ),
+        )
+        .arg(
+            Arg::with_name(REDACT_KEY_KEY)
+                .long("redact-key")
+                .takes_value(true)
+                .number_of_values(1)
+                .help(
+                    "Replaces what is redacted with a hash keyed with this, rather than with \
+                     [REDACTED], so that the same values can still be matched up",
+                ),
+        )
+        .arg(
+            Arg::with_name(OUTPUT_KEY)
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|s| parse_output(&s).map(|_| ()))
+                .conflicts_with_all(&[LOGFMT_KEY, GROK_KEY, COLLAPSE_KEY])
+                .help(
+                    "How to print lines: text, json (an object with the line's text, path, \
+                     line number, byte position and rotation generation) or a template like \
+                     template='{path}:{line}: {text}', which can use the same fields. Line \
+                     numbers start at 0, as with --line. [default: text]",
+                ),
+        )
+        .arg(
+            Arg::with_name(LOGFMT_KEY)
+                .long("logfmt")
+                .takes_value(true)
+                .number_of_values(1)
+                .possible_values(&["pretty", "json"])
+                .help("Parses lines as logfmt, and pretty-prints them or prints them as JSON"),
+        )
+        .arg(
+            Arg::with_name(GROK_KEY)
+                .long("grok")
+                .takes_value(true)
+                .number_of_values(1)
+                .conflicts_with(LOGFMT_KEY)
+                .help(
+                    "Pulls fields out of lines with a grok pattern, e.g. \
+                     \"%{IP:client} %{WORD:method}\", and prints them as JSON. Lines that \
+                     don't match are printed to stderr.",
+                ),
+        )
+        .arg(
+            Arg::with_name(COLLAPSE_KEY)
+                .long("collapse")
+                .takes_value(true)
+                .number_of_values(1)
+                .possible_values(&["exact", "masked"])
+                .conflicts_with_all(&[LOGFMT_KEY, GROK_KEY])
+                .help(
+                    "Prints runs of repeated lines once, with how many times they were \
+                     repeated. With masked, lines that only differ in their numbers and UUIDs \
+                     count as repeats too.",
+                ),
         );
 
     // in case we need to print help
     let mut app_clone = app.clone();
     let matches = app.get_matches();
-    match (matches.value_of(FILE_KEY), matches.value_of(LINE_KEY)) {
-        (Some(file), maybe_line) => {
-            let mut chaser = Chaser::new(&file);
-            if let Some(start_line) = maybe_line {
+    let files = values_of(&matches, FILE_KEY);
+    match files.first() {
+        Some(first) => {
+            let mut chaser = Chaser::new(first);
+            if let Some(start_line) = matches.value_of(LINE_KEY) {
                 chaser.line = Line(start_line.parse()?);
             }
-            chaser.run(|l, _, _| {
-                println!("{}", l);
-                Ok(Control::Continue)
-            })?;
-            Ok(())
+            if let Some(wait) = matches.value_of(INITIAL_NO_FILE_WAIT_KEY) {
+                chaser.initial_no_file_wait = parse_duration(wait)?;
+            }
+            if let Some(attempts) = matches.value_of(INITIAL_NO_FILE_ATTEMPTS_KEY) {
+                chaser.initial_no_file_attempts = Some(attempts.parse()?);
+            }
+            if let Some(wait) = matches.value_of(ROTATION_CHECK_WAIT_KEY) {
+                chaser.rotation_check_wait = parse_duration(wait)?;
+            }
+            if let Some(attempts) = matches.value_of(ROTATION_CHECK_ATTEMPTS_KEY) {
+                chaser.rotation_check_attempts = Some(attempts.parse()?);
+            }
+            if let Some(wait) = matches.value_of(NOT_ROTATED_WAIT_KEY) {
+                chaser.not_rotated_wait = parse_duration(wait)?;
+            }
+            chaser.follow = !matches.is_present(NO_FOLLOW_KEY);
+            if let Some(since) = matches.value_of(SINCE_KEY) {
+                chaser.since = Some(parse_since(since)?);
+            }
+            chaser.filter = Filter {
+                include: values_of(&matches, GREP_KEY),
+                exclude: values_of(&matches, EXCLUDE_KEY),
+                case_insensitive: matches.is_present(IGNORE_CASE_KEY),
+            };
+            let redact_patterns = values_of(&matches, REDACT_PATTERN_KEY);
+            if matches.is_present(REDACT_KEY) || !redact_patterns.is_empty() {
+                chaser.redaction = Some(Redaction {
+                    detectors: if matches.is_present(REDACT_KEY) {
+                        Detector::all()
+                    } else {
+                        Vec::new()
+                    },
+                    patterns: redact_patterns,
+                    replace_with: match matches.value_of(REDACT_KEY_KEY) {
+                        Some(key) => Replacement::KeyedHash(key.to_string()),
+                        None => Replacement::Mask(REDACTION_MASK.to_string()),
+                    },
+                });
+            }
+            chaser.grok = matches.value_of(GROK_KEY).map(Grok::new);
+            let mode = match (matches.value_of(COLLAPSE_KEY), matches.value_of(LOGFMT_KEY)) {
+                (Some(collapse), _) => Mode::Collapsed {
+                    masked: collapse == "masked",
+                },
+                _ if chaser.grok.is_some() => Mode::Grok,
+                (None, Some("json")) => Mode::LogfmtJson,
+                (None, Some(_)) => Mode::LogfmtPretty,
+                (None, None) => Mode::Lines(match matches.value_of(OUTPUT_KEY) {
+                    Some(output) => parse_output(output)?,
+                    None => Output::Text,
+                }),
+            };
+            let options = Options {
+                prefix: matches.is_present(PREFIX_KEY),
+                state_file: matches.value_of(STATE_FILE_KEY).map(PathBuf::from),
+                until: match matches.value_of(UNTIL_KEY) {
+                    Some(pattern) => Some(Regex::new(pattern)?),
+                    None => None,
+                },
+                max_lines: match matches.value_of(MAX_LINES_KEY) {
+                    Some(max_lines) => Some(max_lines.parse()?),
+                    None => None,
+                },
+                idle_timeout: match matches.value_of(IDLE_TIMEOUT_KEY) {
+                    Some(timeout) => Some(parse_duration(timeout)?),
+                    None => None,
+                },
+                pid: match matches.value_of(PID_KEY) {
+                    Some(pid) => Some(pid.parse()?),
+                    None => None,
+                },
+                exec: matches.value_of(EXEC_KEY).map(|exec| exec.to_string()),
+                exec_limit: match matches.value_of(EXEC_LIMIT_KEY) {
+                    Some(limit) => limit.parse()?,
+                    None => 1,
+                },
+                pipe: matches.value_of(PIPE_KEY).map(|pipe| pipe.to_string()),
+                debounce: match matches.value_of(DEBOUNCE_KEY) {
+                    Some(debounce) => Some(parse_duration(debounce)?),
+                    None => None,
+                },
+            };
+            let exit = chase_files(&chaser, &mode, &files, &options)?;
+            Ok(exit.map(Exit::code).unwrap_or(0))
+        }
+        None => {
+            app_clone.print_help()?;
+            Ok(0)
+        }
+    }
+}
+
+// What gets printed for each line (or run of lines), depending on the flags given
+#[derive(Debug, Clone)]
+enum Mode {
+    Lines(Output),
+    LogfmtJson,
+    LogfmtPretty,
+    Grok,
+    Collapsed { masked: bool },
+}
+
+// How the files are chased and printed, beyond what the Chaser and Mode cover
+struct Options {
+    prefix: bool,
+    state_file: Option<PathBuf>,
+    until: Option<Regex>,
+    max_lines: Option<usize>,
+    idle_timeout: Option<Duration>,
+    pid: Option<u32>,
+    exec: Option<String>,
+    exec_limit: usize,
+    pipe: Option<String>,
+    debounce: Option<Duration>,
+}
+
+// Why we stopped chasing before the files were done with, each with its own exit code
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Exit {
+    Matched,
+    MaxLines,
+    IdleTimeout,
+    PidExited,
+}
+
+impl Exit {
+    fn code(self) -> i32 {
+        match self {
+            Exit::MaxLines => 2,
+            Exit::IdleTimeout => 3,
+            Exit::PidExited => 4,
+            Exit::Matched => 5,
+        }
+    }
+}
+
+// Something to print (along with the line, or first line, it came from) and whether it
+// goes to stdout or stderr, or word that the line just printed matched --until
+#[derive(Debug)]
+enum Printed {
+    Out(String, Line),
+    Err(String),
+    Until,
+}
+
+// What the threads chasing each file send back, along with which file it was, and what
+// the thread watching --pid sends once the process is gone
+enum Chased {
+    Printed(usize, Printed),
+    Done(usize, Result<ChaseState, ChaseFailure>),
+    PidExited,
+}
+
+// Chases a file, handing whatever is to be printed to the given function, until a line
+// matches `until`. The function gives back whether it took what it was given; if it
+// didn't, chasing stops, and the state handed back goes back to before it, so that it
+// gets printed on the next run.
+fn chase_file(
+    chaser: &mut Chaser,
+    mode: &Mode,
+    handle: &ControlHandle,
+    until: Option<&Regex>,
+    print: &mut dyn FnMut(Printed) -> Result<bool, ChaseError>,
+) -> Result<ChaseState, ChaseFailure> {
+    let mut declined = None;
+    let mut print_line = |text: &str, printed: Printed, pos: Pos| {
+        let at = match printed {
+            Printed::Out(_, line) => Some((line, pos)),
+            _ => None,
+        };
+        if !print(printed)? {
+            declined = declined.or(at);
+            return Ok(Control::Stop);
+        }
+        match until {
+            Some(until) if until.is_match(text) => {
+                print(Printed::Until)?;
+                Ok(Control::Stop)
+            }
+            _ => Ok(Control::Continue),
         }
-        _ => Ok(app_clone.print_help()?),
+    };
+    let result = match *mode {
+        Mode::Lines(Output::Text) => chaser.run_with_handle(handle, |l, num, pos| {
+            print_line(l, Printed::Out(l.to_string(), num), pos)
+        }),
+        Mode::Lines(Output::Json) => chaser.run_records_with_handle(handle, |record| {
+            let json = json!({
+                "text": record.text,
+                "path": record.path.display().to_string(),
+                "line": record.line.0,
+                "pos": record.pos.0,
+                "generation": record.generation,
+            });
+            print_line(&record.text, Printed::Out(json.to_string(), record.line), record.pos)
+        }),
+        Mode::Lines(Output::Template(ref pieces)) => {
+            chaser.run_records_with_handle(handle, |record| {
+                let rendered = render(pieces, record);
+                print_line(&record.text, Printed::Out(rendered, record.line), record.pos)
+            })
+        }
+        Mode::LogfmtJson => chaser.run_records_with_handle(handle, |record| {
+            let json = to_json(&Logfmt::parse(&record.text))?;
+            print_line(&record.text, Printed::Out(json, record.line), record.pos)
+        }),
+        Mode::LogfmtPretty => chaser.run_records_with_handle(handle, |record| {
+            let pretty = pretty(&Logfmt::parse(&record.text));
+            print_line(&record.text, Printed::Out(pretty, record.line), record.pos)
+        }),
+        Mode::Grok => chaser.run_records_with_handle(handle, |record| {
+            if record.unmatched {
+                print_line(&record.text, Printed::Err(record.text.clone()), record.pos)
+            } else {
+                let json = to_json(&record.fields)?;
+                print_line(&record.text, Printed::Out(json, record.line), record.pos)
+            }
+        }),
+        Mode::Collapsed { masked } => {
+            let flush_after = Duration::from_millis(COLLAPSE_FLUSH_AFTER_MILLIS);
+            chaser.run_collapsed_with_handle(handle, masked, flush_after, |run| {
+                if run.count > 1 {
+                    let collapsed = format!("{} [repeated {} times]", run.text, run.count);
+                    let printed = Printed::Out(collapsed, run.first_line);
+                    print_line(&run.text, printed, run.first_pos)
+                } else {
+                    let printed = Printed::Out(run.text.clone(), run.first_line);
+                    print_line(&run.text, printed, run.first_pos)
+                }
+            })
+        }
+    };
+    let rewind = |mut state: ChaseState| {
+        if let Some((line, pos)) = declined {
+            state.line = line;
+            state.pos = pos;
+        }
+        state
+    };
+    result.map(&rewind).map_err(|mut failure| {
+        failure.state = rewind(failure.state);
+        failure
+    })
+}
+
+// Chases each of the files on its own thread until they're all done with, we get SIGINT
+// or SIGTERM, or one of the Options' reasons to exit comes up, which is handed back. With
+// several files, what comes back is printed either under a header naming the file it came
+// from, like tail -f, or with the file's name in front of every line.
+fn chase_files(
+    chaser: &Chaser,
+    mode: &Mode,
+    files: &[String],
+    options: &Options,
+) -> Result<Option<Exit>, Box<dyn Error>> {
+    let checkpointer = match options.state_file {
+        Some(ref path) => Some(Arc::new(Checkpointer::load(path.clone(), files)?)),
+        None => None,
+    };
+    let handles: Vec<_> = files.iter().map(|_| ControlHandle::new()).collect();
+    {
+        let handles = handles.clone();
+        ctrlc::set_handler(move || {
+            for handle in &handles {
+                handle.stop();
+            }
+        })?;
+    }
+
+    // Every line sent to be printed gets printed, even once we've decided to stop, so
+    // that the states saved match what was printed. To keep to --until and --max-lines,
+    // the threads chasing the files stop themselves (and each other) as they send the
+    // line that does it, and once --max-lines have been sent, no thread sends another.
+    // Only lines for stdout count towards it.
+    let (sender, receiver) = mpsc::sync_channel(PRINT_QUEUE_CAPACITY);
+    let sent_lines = Arc::new(AtomicUsize::new(0));
+    for (index, file) in files.iter().enumerate() {
+        let mut chaser = chaser.clone();
+        chaser.path = PathBuf::from(file);
+        if let Some(ref checkpointer) = checkpointer {
+            chaser.resume_from = checkpointer.state_of(index);
+        }
+        let mode = mode.clone();
+        let handles = handles.clone();
+        let until = options.until.clone();
+        let max_lines = options.max_lines;
+        let sent_lines = sent_lines.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let handle = handles[index].clone();
+            let stop_all = || {
+                for handle in &handles {
+                    handle.stop();
+                }
+            };
+            let result = chase_file(&mut chaser, &mode, &handle, until.as_ref(), &mut |printed| {
+                let last = match (&printed, max_lines) {
+                    (&Printed::Until, _) => true,
+                    (&Printed::Out(..), Some(max_lines)) => {
+                        let sent = sent_lines.fetch_add(1, Ordering::SeqCst);
+                        if sent >= max_lines {
+                            stop_all();
+                            return Ok(false);
+                        }
+                        sent + 1 == max_lines
+                    }
+                    _ => false,
+                };
+                sender.send(Chased::Printed(index, printed))?;
+                if last {
+                    stop_all();
+                }
+                Ok(true)
+            });
+            let _ = sender.send(Chased::Done(index, result));
+        });
+    }
+    if let Some(ref checkpointer) = checkpointer {
+        let checkpointer = checkpointer.clone();
+        let handles = handles.clone();
+        thread::spawn(move || checkpointer.keep_saving(&handles));
+    }
+    if let Some(pid) = options.pid {
+        watch_pid(pid, sender)?;
+    }
+
+    let several = files.len() > 1;
+    let mut commands = Commands::new(options);
+    let mut exit = None;
+    let stop = |why: Exit, exit: &mut Option<Exit>| {
+        if exit.is_none() {
+            *exit = Some(why);
+            for handle in &handles {
+                handle.stop();
+            }
+        }
+    };
+    let mut printed_lines = 0;
+    let mut last_printed = None;
+    let mut failures = Vec::new();
+    let mut done = 0;
+    while done < files.len() {
+        let chased = match (exit, options.idle_timeout) {
+            (None, Some(timeout)) => match receiver.recv_timeout(timeout) {
+                Ok(chased) => chased,
+                Err(RecvTimeoutError::Timeout) => {
+                    stop(Exit::IdleTimeout, &mut exit);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            _ => match receiver.recv() {
+                Ok(chased) => chased,
+                Err(_) => break,
+            },
+        };
+        match chased {
+            Chased::Printed(_, Printed::Until) => stop(Exit::Matched, &mut exit),
+            Chased::Printed(index, printed) => {
+                match printed {
+                    Printed::Out(ref text, _) if !several => println!("{}", text),
+                    Printed::Err(ref text) if !several => eprintln!("{}", text),
+                    Printed::Out(ref text, _) if options.prefix => {
+                        for line in text.trim_end_matches('\n').split('\n') {
+                            println!("{}: {}", files[index], line);
+                        }
+                    }
+                    Printed::Out(ref text, _) => {
+                        if last_printed != Some(index) {
+                            if last_printed.is_some() {
+                                println!();
+                            }
+                            println!("==> {} <==", files[index]);
+                            last_printed = Some(index);
+                        }
+                        println!("{}", text);
+                    }
+                    Printed::Err(ref text) => eprintln!("{}: {}", files[index], text),
+                    Printed::Until => unreachable!("Handled above"),
+                }
+                if let Printed::Out(ref text, line) = printed {
+                    commands.hand_over(text, line, &files[index])?;
+                    printed_lines += 1;
+                    if options.max_lines == Some(printed_lines) {
+                        stop(Exit::MaxLines, &mut exit);
+                    }
+                }
+            }
+            Chased::Done(index, result) => {
+                done += 1;
+                let state = match result {
+                    Ok(state) => state,
+                    Err(failure) => {
+                        if several {
+                            eprintln!("{}: {}", files[index], failure);
+                        }
+                        failures.push(failure.error);
+                        failure.state
+                    }
+                };
+                if let Some(ref checkpointer) = checkpointer {
+                    checkpointer.finish(index, state)?;
+                }
+            }
+            Chased::PidExited => stop(Exit::PidExited, &mut exit),
+        }
+    }
+    commands.finish()?;
+    match failures.len() {
+        0 => Ok(exit),
+        1 if !several => Err(Box::new(failures.remove(0))),
+        failed => Err(format!("Could not chase {} of {} files", failed, files.len()).into()),
+    }
+}
+
+// Checks on the process with the given id every so often, sending word once it's gone
+#[cfg(unix)]
+fn watch_pid(pid: u32, sender: mpsc::SyncSender<Chased>) -> Result<(), Box<dyn Error>> {
+    // Signal 0 only checks whether the process is there (EPERM meaning it is, but isn't
+    // ours)
+    let running = move || {
+        let killed = unsafe { libc::kill(pid as libc::pid_t, 0) };
+        killed == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    };
+    if !running() {
+        return Err(format!("There is no process with id {}", pid).into());
     }
-}
\ No newline at end of file
+    thread::spawn(move || {
+        while running() {
+            thread::sleep(Duration::from_millis(PID_CHECK_INTERVAL_MILLIS));
+        }
+        let _ = sender.send(Chased::PidExited);
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn watch_pid(_: u32, _: mpsc::SyncSender<Chased>) -> Result<(), Box<dyn Error>> {
+    Err("--pid is only supported on Unix".into())
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, ChaseError> {
+    serde_json::to_string(value).map_err(|e| ChaseError::Custom(Box::new(e)))
+}
+
+fn values_of(matches: &ArgMatches, key: &str) -> Vec<String> {
+    matches
+        .values_of(key)
+        .map(|values| values.map(|v| v.to_string()).collect())
+        .unwrap_or_default()
+}
+
+// Takes durations like 250ms, 5s or "1m 30s"
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    humantime::parse_duration(s)
+        .map_err(|e| format!("{:?} is not a duration we understand: {}", s, e))
+}
+
+// Takes RFC3339 timestamps, dates and times without an offset, and times on their own,
+// which are taken to be today's. Those without an offset are in local time; ones that
+// don't exist there (skipped by daylight saving) aren't accepted.
+fn parse_since(s: &str) -> Result<SystemTime, String> {
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(s) {
+        return Ok(SystemTime::from(parsed));
+    }
+    let date_time = [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ].iter()
+        .filter_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .next();
+    let time = || {
+        ["%H:%M:%S%.f", "%H:%M"]
+            .iter()
+            .filter_map(|format| NaiveTime::parse_from_str(s, format).ok())
+            .next()
+            .map(|time| Local::now().date_naive().and_time(time))
+    };
+    date_time
+        .or_else(time)
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(SystemTime::from)
+        .ok_or_else(|| format!("{:?} is not a time we understand", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration as ChronoDuration, NaiveDate, Utc};
+
+    #[test]
+    fn parse_since_test() {
+        let at = |s: &str| DateTime::<Utc>::from(parse_since(s).unwrap());
+        assert_eq!(
+            at("2018-02-03T14:05:00+01:00"),
+            Utc.with_ymd_and_hms(2018, 2, 3, 13, 5, 0).unwrap()
+        );
+        assert_eq!(
+            at("2018-02-03T14:05:00.5Z"),
+            Utc.with_ymd_and_hms(2018, 2, 3, 14, 5, 0).unwrap() + ChronoDuration::milliseconds(500)
+        );
+
+        // Without an offset, it's local time, and without a date, it's today
+        let local = |date: NaiveDate, h, m, s| {
+            let naive = date.and_hms_opt(h, m, s).unwrap();
+            Local.from_local_datetime(&naive).earliest().unwrap().with_timezone(&Utc)
+        };
+        let date = NaiveDate::from_ymd_opt(2018, 2, 3).unwrap();
+        assert_eq!(at("2018-02-03 14:05"), local(date, 14, 5, 0));
+        assert_eq!(at("2018-02-03T14:05:30"), local(date, 14, 5, 30));
+        let before = Local::now().date_naive();
+        let since = at("12:30");
+        let after = Local::now().date_naive();
+        assert!(since == local(before, 12, 30, 0) || since == local(after, 12, 30, 0));
+
+        assert!(parse_since("yesterday").is_err());
+        assert!(parse_since("25:00").is_err());
+    }
+
+    #[test]
+    fn exit_code_test() {
+        let exits = [Exit::MaxLines, Exit::IdleTimeout, Exit::PidExited, Exit::Matched];
+        let codes: Vec<_> = exits.iter().map(|exit| exit.code()).collect();
+        assert_eq!(codes, vec![2, 3, 4, 5]);
+    }
+}
@@ -2,14 +2,22 @@
 
 use data::*;
 use control::*;
-use errors::ChaseError;
+use errors::{ChaseError, ChaseFailure};
+use limit::Admission;
+#[cfg(feature = "timestamps")]
+use timestamp::{find_since, Extractor};
 
 use std::io::{self, BufReader, SeekFrom};
 use std::io::prelude::*;
 use std::fs::File;
-use std::thread::sleep;
+use std::mem;
+use std::cell::Cell;
 use std::time::Duration;
 
+// How many lines may be read between publishing checkpoints on the handle, when we
+// haven't caught up with the end of the file or been stopped in the meantime
+const CHECKPOINT_INTERVAL_LINES: usize = 1000;
+
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 
@@ -59,14 +67,174 @@ impl Chaser {
     /// temp_dir.close().unwrap();
     /// # }
     /// ```
-    pub fn run<F>(&mut self, mut f: F) -> Result<(), ChaseError>
+    pub fn run<F>(&mut self, f: F) -> Result<(), ChaseError>
     where
         F: FnMut(&str, Line, Pos) -> Result<Control, ChaseError>,
     {
-        let (file, file_id) = {
+        self.run_controlled(&ControlHandle::new(), f)
+            .map(|_| ())
+            .map_err(|failure| failure.error)
+    }
+
+    /// Start chasing a file synchronously, like `run`, but with the provided callback
+    /// function being handed a Record of every line read, which also says which file
+    /// it came from, when it was read, and so on.
+    ///
+    /// ```
+    /// # extern crate chase;
+    /// # extern crate tempdir;
+    /// # use chase::*;
+    /// # use tempdir::*;
+    /// # use std::io::Write;
+    /// # use std::fs::OpenOptions;
+    /// # fn main () {
+    /// let temp_dir = TempDir::new("chase-test-sync-records-docs").unwrap();
+    /// let file_path = temp_dir.path().join("test.log");
+    /// let mut chaser = Chaser::new(&file_path);
+    ///
+    /// let mut file_write = OpenOptions::new()
+    ///   .append(true)
+    ///   .create(true)
+    ///   .open(&file_path)
+    ///   .unwrap();
+    ///
+    /// writeln!(file_write, "Hello, world 1").unwrap();
+    /// write!(file_write, "Hello, wor").unwrap();
+    ///
+    /// let mut seen = Vec::new();
+    /// chaser.run_records(|record| {
+    ///     seen.push(record.clone());
+    ///     if seen.len() < 2 {
+    ///         Ok(Control::Continue)
+    ///     } else {
+    ///         Ok(Control::Stop)
+    ///     }
+    /// }).unwrap();
+    ///
+    /// assert_eq!(seen[0].text, "Hello, world 1");
+    /// assert_eq!(seen[0].path, file_path);
+    /// assert_eq!(seen[0].len, 15);
+    /// assert!(!seen[0].partial);
+    /// assert_eq!((seen[1].text.as_str(), seen[1].pos), ("Hello, wor", Pos(15)));
+    /// assert!(seen[1].partial);
+    /// drop(file_write);
+    /// temp_dir.close().unwrap();
+    /// # }
+    /// ```
+    pub fn run_records<F>(&mut self, f: F) -> Result<(), ChaseError>
+    where
+        F: FnMut(&Record) -> Result<Control, ChaseError>,
+    {
+        self.run_records_with_handle(&ControlHandle::new(), f)
+            .map(|_| ())
+            .map_err(|failure| failure.error)
+    }
+
+    /// Start chasing a file synchronously, like `run`, but with a ControlHandle that can be
+    /// used from other threads to control the watch loop and stop it (in which case this
+    /// returns `Ok`), and on which lines skipped because of an ErrorPolicy are reported.
+    ///
+    /// Whether things went well or not, the state the chaser stopped in is handed back.
+    pub fn run_with_handle<F>(
+        &mut self,
+        handle: &ControlHandle,
+        f: F,
+    ) -> Result<ChaseState, ChaseFailure>
+    where
+        F: FnMut(&str, Line, Pos) -> Result<Control, ChaseError>,
+    {
+        self.run_controlled(handle, f)
+    }
+
+    /// Start chasing a file synchronously, like `run_records`, but with a ControlHandle, as
+    /// with `run_with_handle`.
+    ///
+    /// Whether things went well or not, the state the chaser stopped in is handed back.
+    pub fn run_records_with_handle<F>(
+        &mut self,
+        handle: &ControlHandle,
+        f: F,
+    ) -> Result<ChaseState, ChaseFailure>
+    where
+        F: FnMut(&Record) -> Result<Control, ChaseError>,
+    {
+        self.run_controlled(handle, EachRecord(f))
+    }
+
+    /// Start chasing a file synchronously, applying Controls queued up on the given
+    /// ControlHandle, and exiting the watch loop with `Ok` as soon as it is stopped.
+    ///
+    /// Whether things went well or not, the state the chaser stopped in is handed back.
+    pub(crate) fn run_controlled<D>(
+        &mut self,
+        handle: &ControlHandle,
+        mut deliver: D,
+    ) -> Result<ChaseState, ChaseFailure>
+    where
+        D: Deliver,
+    {
+        let initial_state = self.initial_state();
+        let mut running = match self.start_chasing(handle, &initial_state) {
+            Ok(Some(running)) => running,
+            Ok(None) => return Ok(initial_state),
+            Err(error) => {
+                return Err(ChaseFailure {
+                    error,
+                    state: initial_state,
+                })
+            }
+        };
+        let result = chase(&mut running, &mut deliver).and_then(|_| deliver.finish());
+        // Without finishing, lines the deliverer held on to are lost, so the state goes
+        // back to before them
+        running.held_from = deliver.held_from();
+        running.checkpoint();
+        let state = running.handed_off();
+        match result {
+            Ok(()) => Ok(state),
+            Err(error) => Err(ChaseFailure { error, state }),
+        }
+    }
+
+    // The state we're starting out in, before the file has been opened
+    pub(crate) fn initial_state(&self) -> ChaseState {
+        self.resume_from.clone().unwrap_or(ChaseState {
+            file_id: None,
+            line: self.line,
+            pos: Pos(0),
+            lines_read: 0,
+            rotations: 0,
+            seq: 0,
+        })
+    }
+
+    // Opens the file and moves to where we should start chasing from. Gives back None if
+    // we were stopped before the file could be opened.
+    pub(crate) fn start_chasing<'a>(
+        &'a mut self,
+        handle: &ControlHandle,
+        initial_state: &ChaseState,
+    ) -> Result<Option<Chasing<'a>>, ChaseError> {
+        #[cfg(feature = "filter")]
+        let filter = self.filter.compile()?;
+        #[cfg(feature = "timestamps")]
+        let timestamps = Extractor::compile(&self.timestamps)?;
+        #[cfg(feature = "grok")]
+        let grok = match self.grok {
+            Some(ref grok) => Some(grok.compile()?),
+            None => None,
+        };
+        let sampler = self.sampling.map(|sampling| sampling.sampler());
+        let limiter = self.rate_limit.as_ref().map(|limit| limit.limiter());
+        #[cfg(feature = "redact")]
+        let redactor = match self.redaction {
+            Some(ref redaction) => redaction.compile()?,
+            None => None,
+        };
+        let maybe_file = {
             let attempts = self.initial_no_file_attempts;
             let wait = self.initial_no_file_wait;
-            try_until::<_, ChaseError, _>(
+            try_until(
                 || {
                     let file = File::open(&self.path)?;
                     let file_id = get_file_id(&file)?;
@@ -74,93 +242,486 @@ impl Chaser {
                 },
                 attempts,
                 Some(wait),
-            )?
+                handle,
+            ).map_err(|e: io::Error| match e.kind() {
+                io::ErrorKind::NotFound => ChaseError::FileNotFound {
+                    path: self.path.clone(),
+                    attempts: attempts.unwrap_or_default(),
+                },
+                _ => ChaseError::IoError(e),
+            })?
+        };
+        let (file, file_id) = match maybe_file {
+            Some(opened) => opened,
+            None => return Ok(None),
+        };
+        // Only resume from a previous position if we're still looking at the same file
+        // and it hasn't been truncated in the meantime
+        let resume_at = if initial_state.file_id == Some(file_id)
+            && file.metadata()?.len() >= initial_state.pos.0
+        {
+            Some((initial_state.line, initial_state.pos))
+        } else {
+            None
         };
-        // Create a BufReader and skip to the proper line number while
-        // keeping track of byte-position
         let mut reader = BufReader::new(file);
-        let mut current_line = Line(0);
-        let mut current_pos = Pos(0);
-        let mut buffer = String::new();
-        'skip_to_line: while current_line < self.line {
-            let read_bytes = reader.read_line(&mut buffer)? as u64;
-            if read_bytes > 0 {
-                current_pos.0 += read_bytes;
-                current_line.0 += 1;
-                buffer.clear();
-                reader.seek(SeekFrom::Start(current_pos.0))?;
-            } else {
-                break 'skip_to_line;
+        #[cfg(feature = "timestamps")]
+        let since = self.since;
+        #[cfg(not(feature = "timestamps"))]
+        let since = None::<::std::time::SystemTime>;
+        let (current_line, current_pos) = match (resume_at, since) {
+            (Some((line, pos)), _) => {
+                reader.seek(SeekFrom::Start(pos.0))?;
+                (line, pos)
             }
-        }
+            #[cfg(feature = "timestamps")]
+            (None, Some(since)) => {
+                let (line, pos) = find_since(&mut reader, &self.timestamps, since)?;
+                reader.seek(SeekFrom::Start(pos.0))?;
+                (line, pos)
+            }
+            (None, _) => {
+                // Skip to the proper line number while keeping track of byte-position
+                let mut current_line = Line(0);
+                let mut current_pos = Pos(0);
+                let mut skipped = Vec::new();
+                'skip_to_line: while current_line < self.line {
+                    let read_bytes = reader.read_until(b'\n', &mut skipped)? as u64;
+                    if read_bytes > 0 {
+                        current_pos.0 += read_bytes;
+                        current_line.0 += 1;
+                        skipped.clear();
+                        reader.seek(SeekFrom::Start(current_pos.0))?;
+                    } else {
+                        break 'skip_to_line;
+                    }
+                }
+                (current_line, current_pos)
+            }
+        };
 
-        let mut running = Chasing {
+        Ok(Some(Chasing {
             chaser: self,
             file_id,
             reader,
-            buffer,
+            buffer: String::new(),
             pos: current_pos,
             line: current_line,
-        };
-        chase(&mut running, &mut f, false)
+            lines_read: initial_state.lines_read,
+            rotations: initial_state.rotations,
+            seq: initial_state.seq,
+            read_retries: 0,
+            rotated_to: None,
+            handle: handle.clone(),
+            #[cfg(feature = "filter")]
+            filter,
+            #[cfg(feature = "timestamps")]
+            timestamps,
+            #[cfg(feature = "grok")]
+            grok,
+            sampler,
+            limiter,
+            #[cfg(feature = "redact")]
+            redactor,
+            undelivered: Cell::new(false),
+            // So the first read gets checkpointed
+            unchecked_lines: CHECKPOINT_INTERVAL_LINES,
+            held_from: None,
+        }))
     }
 }
 
-fn chase<F>(running: &mut Chasing, f: &mut F, grabbing_remainder: bool) -> Result<(), ChaseError>
+/// Receives what gets read while chasing a file
+pub(crate) trait Deliver {
+    /// Called with every line read, while it is still in the chaser's buffer
+    fn line(&mut self, running: &Chasing, bytes_read: usize) -> Result<Control, ChaseError>;
+
+    /// Called whenever we've caught up with the end of the file, before waiting for more
+    fn idle(&mut self) -> Result<Control, ChaseError> {
+        Ok(Control::Continue)
+    }
+
+    /// Called once when chasing ends without errors
+    fn finish(&mut self) -> Result<(), ChaseError> {
+        Ok(())
+    }
+
+    /// The state from before the first of the lines it's holding on to rather than having
+    /// handed off yet, if any, so that they aren't missed when resuming
+    fn held_from(&self) -> Option<ChaseState> {
+        None
+    }
+}
+
+impl<F> Deliver for F
 where
     F: FnMut(&str, Line, Pos) -> Result<Control, ChaseError>,
 {
-    'reading: loop {
-        'read_to_eof: loop {
-            let bytes_read = running.reader.read_line(&mut running.buffer)?;
+    fn line(&mut self, running: &Chasing, _: usize) -> Result<Control, ChaseError> {
+        running.call_back(|| self(running.text(), running.line, running.pos))
+    }
+}
+
+// Hands a Record of every line read to the wrapped function
+pub(crate) struct EachRecord<F>(pub(crate) F);
+
+impl<F> Deliver for EachRecord<F>
+where
+    F: FnMut(&Record) -> Result<Control, ChaseError>,
+{
+    fn line(&mut self, running: &Chasing, bytes_read: usize) -> Result<Control, ChaseError> {
+        let record = running.record(bytes_read);
+        let f = &mut self.0;
+        running.call_back(|| f(&record))
+    }
+}
+
+fn chase<D>(running: &mut Chasing, deliver: &mut D) -> Result<(), ChaseError>
+where
+    D: Deliver,
+{
+    loop {
+        let next = running.next_line(&mut |running| {
+            let control = deliver.idle()?;
+            running.held_from = deliver.held_from();
+            Ok(control)
+        })?;
+        let bytes_read = match next {
+            Some(bytes_read) => bytes_read,
+            None => break,
+        };
+        let control = deliver.line(running, bytes_read)?;
+        running.held_from = deliver.held_from();
+        running.advance(bytes_read)?;
+        if running.apply(control)? == Control::Stop {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Chasing<'a> {
+    /// Reads the next line into the buffer, following rotations and waiting for more to
+    /// be written as needed, and gives back how many bytes were read.
+    ///
+    /// Whenever we catch up with the end of the file, `on_idle` is called before waiting.
+    /// Gives back None if we've been asked to stop, either by the stop handle or `on_idle`,
+    /// or if we got to the end of the file and are not to follow it.
+    pub(crate) fn next_line(
+        &mut self,
+        on_idle: &mut dyn FnMut(&mut Chasing<'a>) -> Result<Control, ChaseError>,
+    ) -> Result<Option<usize>, ChaseError> {
+        let next = self.read_next_line(on_idle)?;
+        if next.is_none() {
+            self.checkpoint();
+        }
+        Ok(next)
+    }
+
+    fn read_next_line(
+        &mut self,
+        on_idle: &mut dyn FnMut(&mut Chasing<'a>) -> Result<Control, ChaseError>,
+    ) -> Result<Option<usize>, ChaseError> {
+        loop {
+            if self.handle.is_stopped() {
+                return Ok(None);
+            }
+            while let Some(control) = self.handle.next_pending() {
+                if self.apply(control)? == Control::Stop {
+                    return Ok(None);
+                }
+            }
+            // Publishing takes the handle's lock, so isn't done for every line
+            if self.unchecked_lines >= CHECKPOINT_INTERVAL_LINES {
+                self.checkpoint();
+            }
+            self.unchecked_lines += 1;
+            let bytes_read = match self.read_line() {
+                Ok(bytes_read) => {
+                    self.read_retries = 0;
+                    bytes_read
+                }
+                Err(error) => {
+                    let (policy, undecodable) = match error {
+                        ChaseError::InvalidEncoding { .. } => (
+                            self.chaser.on_decoding_error,
+                            self.reader.stream_position()? - self.pos.0,
+                        ),
+                        ChaseError::IoError(ref e) if is_transient(e) => {
+                            (self.chaser.on_read_error, 0)
+                        }
+                        _ => return Err(error),
+                    };
+                    match policy.recover(error, self.read_retries, &self.handle)? {
+                        Recovery::Stopped => return Ok(None),
+                        Recovery::Retry => {
+                            self.read_retries += 1;
+                            self.reader.seek(SeekFrom::Start(self.pos.0))?;
+                        }
+                        Recovery::Skip if undecodable > 0 => self.skip(undecodable as usize)?,
+                        Recovery::Skip => {
+                            self.reader.seek(SeekFrom::Start(self.pos.0))?;
+                            if self.handle.wait(self.chaser.not_rotated_wait) {
+                                return Ok(None);
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
             if bytes_read > 0 {
-                let control = f(
-                    running.buffer.trim_right_matches('\n'),
-                    running.line,
-                    running.pos,
-                )?;
-                if control == Control::Stop {
-                    break 'reading;
+                #[cfg(feature = "filter")]
+                {
+                    let keep = self.filter
+                        .as_ref()
+                        .map(|filter| filter.keeps(self.text()))
+                        .unwrap_or(true);
+                    if !keep {
+                        self.handle.count_filtered_lines(1);
+                        self.skip(bytes_read)?;
+                        continue;
+                    }
+                }
+                match self.admit(bytes_read) {
+                    Admission::Admit => {
+                        #[cfg(feature = "redact")]
+                        self.redact();
+                        return Ok(Some(bytes_read));
+                    }
+                    Admission::Drop => {
+                        self.skip(bytes_read)?;
+                        continue;
+                    }
+                    Admission::Stopped => return Ok(None),
                 }
-                running.buffer.clear();
-                running.line.0 += 1;
-                running.pos.0 += bytes_read as u64;
-                running.reader.seek(SeekFrom::Start(running.pos.0))?;
-            } else {
-                break 'read_to_eof; // no bytes read -> EOF
             }
-        }
-
-        if grabbing_remainder {
-            break 'reading;
-        } else {
+            // No bytes read -> EOF
+            if let Some((new_file, new_file_id)) = self.rotated_to.take() {
+                // We've read the rest of the rotated file, so restart reading from the
+                // top of the new one
+                self.line = Line(0);
+                self.pos = Pos(0);
+                self.rotations += 1;
+                self.file_id = new_file_id;
+                self.reader = BufReader::new(new_file);
+                continue;
+            }
+            if !self.chaser.follow {
+                return Ok(None);
+            }
+            // Checkpointed once on_idle has had the chance to hand off what's held on to
+            let control = on_idle(self)?;
+            self.checkpoint();
+            if control == Control::Stop {
+                return Ok(None);
+            }
             let rotation_status = {
-                let attempts = running.chaser.rotation_check_attempts;
-                let wait = running.chaser.rotation_check_wait;
-                try_until(|| check_rotation_status(running), attempts, Some(wait))?
+                let attempts = self.chaser.rotation_check_attempts;
+                let wait = self.chaser.rotation_check_wait;
+                let handle = self.handle.clone();
+                try_until(|| check_rotation_status(self), attempts, Some(wait), &handle)
+                    .map_err(|source| ChaseError::RotationCheckExhausted {
+                        path: self.chaser.path.clone(),
+                        attempts: attempts.unwrap_or_default(),
+                        line: self.line,
+                        pos: self.pos,
+                        source,
+                    })?
             };
             match rotation_status {
-                RotationStatus::Rotated {
-                    file: new_file,
-                    file_id: new_file_id,
-                } => {
-                    // Read the rest of the same file
-                    chase(running, f, true)?;
-                    // Restart reading loop, but read from the top
-                    running.line = Line(0);
-                    running.pos = Pos(0);
-                    running.file_id = new_file_id;
-                    running.reader = BufReader::new(new_file);
-                    continue 'reading;
+                None => return Ok(None),
+                Some(RotationStatus::Rotated { file, file_id }) => {
+                    // Read the rest of the same file before moving on
+                    self.rotated_to = Some((file, file_id));
                 }
-                RotationStatus::NotRotated => {
-                    sleep(running.chaser.not_rotated_wait);
-                    continue 'reading;
+                Some(RotationStatus::NotRotated) => {
+                    if self.handle.wait_for_controls(self.chaser.not_rotated_wait) {
+                        return Ok(None);
+                    }
                 }
             }
         }
     }
-    Ok(())
+
+    // Reads up to the next newline (or the end of the file) into the buffer, giving back
+    // how many bytes were read, and failing if they aren't valid UTF-8.
+    fn read_line(&mut self) -> Result<usize, ChaseError> {
+        let mut bytes = mem::take(&mut self.buffer).into_bytes();
+        bytes.clear();
+        let bytes_read = self.reader.read_until(b'\n', &mut bytes)?;
+        match String::from_utf8(bytes) {
+            Ok(text) => {
+                self.buffer = text;
+                Ok(bytes_read)
+            }
+            Err(e) => Err(ChaseError::InvalidEncoding {
+                line: self.line,
+                pos: self.pos,
+                source: e.utf8_error(),
+            }),
+        }
+    }
+
+    /// Calls back with the line that was just read, applying the chaser's policy for
+    /// callback errors
+    pub(crate) fn call_back<F>(&self, f: F) -> Result<Control, ChaseError>
+    where
+        F: FnMut() -> Result<Control, ChaseError>,
+    {
+        call_back(
+            self.chaser.on_callback_error,
+            &self.handle,
+            self.line,
+            self.pos,
+            f,
+        )
+    }
+
+    /// Publishes the state to resume from as the handle's checkpoint, which is from before
+    /// any lines the deliverer is still holding on to
+    pub(crate) fn checkpoint(&mut self) {
+        self.handle.set_checkpoint(self.handed_off());
+        self.unchecked_lines = 0;
+    }
+
+    /// Moves past the line that was just read and delivered (or skipped, if it was marked
+    /// as undelivered)
+    pub(crate) fn advance(&mut self, bytes_read: usize) -> Result<(), ChaseError> {
+        if !self.undelivered.replace(false) {
+            self.seq += 1;
+        }
+        self.skip(bytes_read)
+    }
+
+    // Moves past the line that was just read, without it counting as delivered
+    fn skip(&mut self, bytes_read: usize) -> Result<(), ChaseError> {
+        self.buffer.clear();
+        self.line.0 += 1;
+        self.pos.0 += bytes_read as u64;
+        self.lines_read += 1;
+        self.reader.seek(SeekFrom::Start(self.pos.0))?;
+        Ok(())
+    }
+
+    /// Applies the given Control, giving back Control::Stop if we should stop, and
+    /// Control::Continue otherwise.
+    pub(crate) fn apply(&mut self, control: Control) -> Result<Control, ChaseError> {
+        match control {
+            Control::Stop => return Ok(Control::Stop),
+            Control::Continue => (),
+            Control::SeekTo(Pos(pos)) => self.seek_to(pos)?,
+            Control::Rewind => self.seek_to(0)?,
+            Control::SkipToEnd => self.seek_to(u64::MAX)?,
+            Control::PauseFor(duration) => {
+                if self.handle.wait(duration) {
+                    return Ok(Control::Stop);
+                }
+            }
+        }
+        Ok(Control::Continue)
+    }
+
+    // Moves to the given byte-position in the current file (or its end, if it isn't that
+    // long), counting lines on the way so that Line stays accurate.
+    fn seek_to(&mut self, target: u64) -> Result<(), ChaseError> {
+        self.buffer.clear();
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut line = Line(0);
+        let mut pos = Pos(0);
+        let mut bytes = Vec::new();
+        while pos.0 < target {
+            bytes.clear();
+            let bytes_read = (&mut self.reader)
+                .take(target - pos.0)
+                .read_until(b'\n', &mut bytes)?;
+            pos.0 += bytes_read as u64;
+            if bytes_read == 0 || bytes.last() != Some(&b'\n') {
+                // Either the end of the file, or we've stopped somewhere in the middle of
+                // a line, which still counts as that line
+                break;
+            }
+            line.0 += 1;
+        }
+        self.line = line;
+        self.pos = pos;
+        self.reader.seek(SeekFrom::Start(pos.0))?;
+        Ok(())
+    }
+}
+
+/// Calls back with the line at the given Line and Pos (or the first line of a batch),
+/// retrying or skipping it if the callback fails, according to the given policy.
+pub(crate) fn call_back<F>(
+    policy: ErrorPolicy,
+    handle: &ControlHandle,
+    line: Line,
+    pos: Pos,
+    mut f: F,
+) -> Result<Control, ChaseError>
+where
+    F: FnMut() -> Result<Control, ChaseError>,
+{
+    let mut retries = 0;
+    loop {
+        let error = match f() {
+            Ok(control) => return Ok(control),
+            Err(error) => ChaseError::CallbackError {
+                line,
+                pos,
+                error: Box::new(error),
+            },
+        };
+        match policy.recover(error, retries, handle)? {
+            Recovery::Retry => retries += 1,
+            Recovery::Skip => return Ok(Control::Continue),
+            Recovery::Stopped => return Ok(Control::Stop),
+        }
+    }
+}
+
+// What to do after an error, according to an ErrorPolicy
+enum Recovery {
+    Retry,
+    Skip,
+    // Stop was signalled while waiting to retry
+    Stopped,
+}
+
+impl ErrorPolicy {
+    // Decides what to do about an error, given how many times we've already retried,
+    // waiting out the backoff before a retry. Skipped errors get reported on the handle,
+    // and errors we should stop with are handed back.
+    fn recover(
+        self,
+        error: ChaseError,
+        retries: usize,
+        handle: &ControlHandle,
+    ) -> Result<Recovery, ChaseError> {
+        match self {
+            ErrorPolicy::Stop => Err(error),
+            ErrorPolicy::Skip => {
+                handle.report_skipped(error);
+                Ok(Recovery::Skip)
+            }
+            ErrorPolicy::Retry { attempts, backoff } => {
+                if retries >= attempts {
+                    Err(error)
+                } else if handle.wait(backoff * 2u32.saturating_pow(retries as u32)) {
+                    Ok(Recovery::Stopped)
+                } else {
+                    Ok(Recovery::Retry)
+                }
+            }
+        }
+    }
+}
+
+// Whether a read error may well go away if we try again
+#[cfg(unix)]
+fn is_transient(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::Interrupted
+        || error.raw_os_error() == Some(libc::ESTALE)
+        || error.raw_os_error() == Some(libc::EIO)
 }
 
 fn check_rotation_status(running: &mut Chasing) -> Result<RotationStatus, io::Error> {
@@ -173,12 +734,14 @@ fn check_rotation_status(running: &mut Chasing) -> Result<RotationStatus, io::Er
     }
 }
 
-// Will go at least once, max attempts set to None means try until successful
+// Will go at least once, max attempts set to None means try until successful.
+// Gives back Ok(None) if the stop handle was stopped while waiting between attempts.
 fn try_until<R, E, F>(
     mut f: F,
     max_attempts: Option<usize>,
     delay: Option<Duration>,
-) -> Result<R, E>
+    handle: &ControlHandle,
+) -> Result<Option<R>, E>
 where
     F: FnMut() -> Result<R, E>,
 {
@@ -189,12 +752,12 @@ where
             tries += 1;
         }
         if current_try.is_err() && max_attempts.map(|until| tries < until).unwrap_or(true) {
-            if let Some(duration) = delay {
-                sleep(duration);
+            if handle.wait(delay.unwrap_or_default()) {
+                return Ok(None);
             }
             continue;
         } else {
-            return current_try;
+            return current_try.map(Some);
         }
     }
 }
@@ -213,32 +776,38 @@ enum RotationStatus {
 #[cfg(test)]
 mod tests {
 
-    use sync::try_until;
+    use sync::{try_until, Deliver};
     use data::*;
     use control::*;
+    use errors::ChaseError;
     use tempdir::*;
+    use std::error::Error;
     use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
 
     use std::fs::OpenOptions;
 
     #[test]
     fn try_until_test() {
-        let result_0: Result<i32, ()> = try_until(|| Ok(1), None, None);
-        assert_eq!(result_0, Ok(1));
-        let result_1: Result<i32, ()> = try_until(|| Ok(1), Some(1), None);
-        assert_eq!(result_1, Ok(1));
+        let stop = ControlHandle::new();
+        let result_0: Result<Option<i32>, ()> = try_until(|| Ok(1), None, None, &stop);
+        assert_eq!(result_0, Ok(Some(1)));
+        let result_1: Result<Option<i32>, ()> = try_until(|| Ok(1), Some(1), None, &stop);
+        assert_eq!(result_1, Ok(Some(1)));
         let mut tries = 0;
-        let result_2: Result<i32, ()> = try_until(
+        let result_2: Result<Option<i32>, ()> = try_until(
             || {
                 tries += 1;
                 Err(())
             },
             Some(1),
             None,
+            &stop,
         );
         assert_eq!(tries, 1);
         assert_eq!(result_2, Err(()));
-        let result_3: Result<i32, ()> = try_until(
+        let result_3: Result<Option<i32>, ()> = try_until(
             || {
                 tries += 1;
                 if tries < 1000 {
@@ -249,8 +818,13 @@ mod tests {
             },
             Some(999),
             None,
+            &stop,
         );
-        assert_eq!(result_3, Ok(1));
+        assert_eq!(result_3, Ok(Some(1)));
+        stop.stop();
+        let result_4: Result<Option<i32>, ()> =
+            try_until(|| Err(()), None, Some(Duration::from_secs(60)), &stop);
+        assert_eq!(result_4, Ok(None));
     }
 
     #[test]
@@ -260,15 +834,14 @@ mod tests {
         let mut chaser = Chaser::new(&file_path);
 
         let mut file_write = OpenOptions::new()
-            .write(true)
             .append(true)
             .create(true)
             .open(&file_path)
             .unwrap();
 
-        write!(file_write, "Hello, world 1\n").unwrap();
-        write!(file_write, "Hello, world 2\n").unwrap();
-        write!(file_write, "Hello, world 3\n").unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        writeln!(file_write, "Hello, world 3").unwrap();
 
         let mut seen = Vec::with_capacity(3);
 
@@ -295,4 +868,406 @@ mod tests {
         drop(file_write);
         temp_dir.close().unwrap();
     }
+
+    #[test]
+    fn run_controls_test() {
+        let temp_dir = TempDir::new("chase-test-sync-controls").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        writeln!(file_write, "Hello, world 3").unwrap();
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(250));
+            writeln!(file_write, "Hello, world 4").unwrap();
+        });
+
+        let mut controls = vec![
+            Control::SeekTo(Pos(30)),
+            Control::Rewind,
+            Control::SeekTo(Pos(20)),
+            Control::PauseFor(Duration::from_millis(10)),
+            Control::SkipToEnd,
+            Control::Stop,
+        ].into_iter();
+        let mut seen = Vec::new();
+        chaser
+            .run(|line, num, pos| {
+                seen.push((line.to_string(), num, pos));
+                Ok(controls.next().unwrap())
+            })
+            .unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(
+            seen,
+            vec![
+                ("Hello, world 1".to_string(), Line(0), Pos(0)),
+                ("Hello, world 3".to_string(), Line(2), Pos(30)),
+                ("Hello, world 1".to_string(), Line(0), Pos(0)),
+                (", world 2".to_string(), Line(1), Pos(20)),
+                ("Hello, world 3".to_string(), Line(2), Pos(30)),
+                ("Hello, world 4".to_string(), Line(3), Pos(45)),
+            ]
+        );
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn run_records_resume_test() {
+        let temp_dir = TempDir::new("chase-test-sync-records").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        // The file has changed since this state was saved, so we start from the top but
+        // keep counting from where it left off
+        chaser.resume_from = Some(ChaseState {
+            file_id: None,
+            line: Line(7),
+            pos: Pos(100),
+            lines_read: 41,
+            rotations: 2,
+            seq: 41,
+        });
+        let mut seen = Vec::new();
+        chaser
+            .run_records(|record| {
+                seen.push((record.text.clone(), record.generation, record.line, record.seq));
+                Ok(if seen.len() < 2 {
+                    Control::Continue
+                } else {
+                    Control::Stop
+                })
+            })
+            .unwrap();
+
+        assert_eq!(
+            seen,
+            vec![
+                ("Hello, world 1".to_string(), 2, Line(0), 41),
+                ("Hello, world 2".to_string(), 2, Line(1), 42),
+            ]
+        );
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn run_records_checkpoint_test() {
+        let temp_dir = TempDir::new("chase-test-sync-checkpoint").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        let handle = ControlHandle::new();
+        assert_eq!(handle.checkpoint(), None);
+        let mut checkpoints = Vec::new();
+        let state = chaser
+            .run_records_with_handle(&handle, |record| {
+                let checkpoint = handle.checkpoint().unwrap();
+                checkpoints.push((checkpoint.line, checkpoint.pos, checkpoint.seq));
+                // Lines are only checkpointed once they have been delivered
+                assert!(checkpoint.pos <= record.pos);
+                Ok(if checkpoints.len() < 2 {
+                    Control::Continue
+                } else {
+                    Control::Stop
+                })
+            })
+            .unwrap();
+
+        // Not every line is checkpointed, but stopping is
+        assert_eq!(
+            checkpoints,
+            vec![(Line(0), Pos(0), 0), (Line(0), Pos(0), 0)]
+        );
+        assert_eq!((state.line, state.pos, state.seq), (Line(2), Pos(30), 2));
+        assert_eq!(handle.checkpoint(), Some(state.clone()));
+
+        // As is catching up with the end of the file, once the deliverer has been idle
+        writeln!(file_write, "Hello, world 3").unwrap();
+        chaser.resume_from = Some(state);
+        let handle = ControlHandle::new();
+        let mut idle_checkpoints = Vec::new();
+        struct CheckpointOnIdle<'a>(&'a ControlHandle, &'a mut Vec<Line>);
+        impl<'a> Deliver for CheckpointOnIdle<'a> {
+            fn line(&mut self, _: &Chasing, _: usize) -> Result<Control, ChaseError> {
+                Ok(Control::Continue)
+            }
+
+            fn idle(&mut self) -> Result<Control, ChaseError> {
+                self.1.push(self.0.checkpoint().unwrap().line);
+                Ok(if self.1.len() < 2 {
+                    Control::Continue
+                } else {
+                    Control::Stop
+                })
+            }
+        }
+        chaser
+            .run_controlled(&handle, CheckpointOnIdle(&handle, &mut idle_checkpoints))
+            .unwrap();
+        assert_eq!(idle_checkpoints, vec![Line(2), Line(3)]);
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn run_errors_test() {
+        let temp_dir = TempDir::new("chase-test-sync-errors").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.initial_no_file_attempts = Some(2);
+        chaser.initial_no_file_wait = Duration::from_millis(1);
+
+        match chaser.run(|_, _, _| Ok(Control::Continue)) {
+            Err(ChaseError::FileNotFound { ref path, attempts }) => {
+                assert_eq!((path, attempts), (&file_path, 2))
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        file_write.write_all(b"Hello, \xffworld 2\n").unwrap();
+
+        match chaser.run(|_, _, _| Err(ChaseError::Custom("nope".into()))) {
+            Err(e @ ChaseError::CallbackError { .. }) => {
+                assert_eq!(e.to_string(), "Callback failed on line 0 (byte 0): nope");
+                assert_eq!(e.source().unwrap().to_string(), "nope");
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        match chaser.run(|_, _, _| Ok(Control::Continue)) {
+            Err(ChaseError::InvalidEncoding { line, pos, source }) => {
+                assert_eq!((line, pos), (Line(1), Pos(15)));
+                assert_eq!(source.valid_up_to(), 7);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn callback_error_policy_test() {
+        let temp_dir = TempDir::new("chase-test-sync-callback-policy").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        writeln!(file_write, "Hello, world 3").unwrap();
+
+        // Line 0 goes through on its third try, line 1 never does
+        chaser.on_callback_error = ErrorPolicy::Retry {
+            attempts: 2,
+            backoff: Duration::from_millis(1),
+        };
+        let mut calls = Vec::new();
+        let result = chaser.run(|_, num, _| {
+            calls.push(num);
+            if calls.len() == 3 {
+                Ok(Control::Continue)
+            } else {
+                Err(ChaseError::Custom("nope".into()))
+            }
+        });
+        match result {
+            Err(ChaseError::CallbackError { line, .. }) => assert_eq!(line, Line(1)),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(
+            calls,
+            vec![Line(0), Line(0), Line(0), Line(1), Line(1), Line(1)]
+        );
+
+        chaser.on_callback_error = ErrorPolicy::Skip;
+        let handle = ControlHandle::new();
+        let mut seen = Vec::new();
+        let state = chaser
+            .run_with_handle(&handle, |line, num, _| {
+                if num == Line(1) {
+                    Err(ChaseError::Custom("nope".into()))
+                } else {
+                    seen.push(line.to_string());
+                    Ok(if num == Line(2) {
+                        Control::Stop
+                    } else {
+                        Control::Continue
+                    })
+                }
+            })
+            .unwrap();
+        assert_eq!(
+            seen,
+            vec!["Hello, world 1".to_string(), "Hello, world 3".to_string()]
+        );
+        assert_eq!(state.line, Line(3));
+        assert_eq!(handle.skipped_lines(), 1);
+        let skipped = handle.take_skipped();
+        assert_eq!(
+            skipped[0].to_string(),
+            "Callback failed on line 1 (byte 15): nope"
+        );
+        assert!(handle.take_skipped().is_empty());
+
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn decoding_error_policy_test() {
+        let temp_dir = TempDir::new("chase-test-sync-decoding-policy").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.on_decoding_error = ErrorPolicy::Skip;
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        file_write.write_all(b"Hello, \xffworld 2\n").unwrap();
+        writeln!(file_write, "Hello, world 3").unwrap();
+
+        let handle = ControlHandle::new();
+        let mut seen = Vec::new();
+        chaser
+            .run_with_handle(&handle, |line, num, pos| {
+                seen.push((line.to_string(), num, pos));
+                Ok(if seen.len() < 2 {
+                    Control::Continue
+                } else {
+                    Control::Stop
+                })
+            })
+            .unwrap();
+
+        assert_eq!(
+            seen,
+            vec![
+                ("Hello, world 1".to_string(), Line(0), Pos(0)),
+                ("Hello, world 3".to_string(), Line(2), Pos(31)),
+            ]
+        );
+        match handle.take_skipped().pop() {
+            Some(ChaseError::InvalidEncoding { line, pos, .. }) => {
+                assert_eq!((line, pos), (Line(1), Pos(15)))
+            }
+            other => panic!("Unexpected report {:?}", other),
+        }
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn run_filter_test() {
+        let temp_dir = TempDir::new("chase-test-sync-filter").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.filter = Filter {
+            include: vec!["^(error|warn)".to_string()],
+            exclude: vec!["ignore me".to_string()],
+            case_insensitive: true,
+        };
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "info: Hello, world 1").unwrap();
+        writeln!(file_write, "ERROR: Hello, world 2").unwrap();
+        writeln!(file_write, "warn: Hello, world 3 (ignore me)").unwrap();
+        writeln!(file_write, "warn: Hello, world 4").unwrap();
+
+        let handle = ControlHandle::new();
+        let mut seen = Vec::new();
+        let state = chaser
+            .run_with_handle(&handle, |line, num, _| {
+                seen.push((line.to_string(), num));
+                Ok(if seen.len() < 2 {
+                    Control::Continue
+                } else {
+                    Control::Stop
+                })
+            })
+            .unwrap();
+
+        assert_eq!(
+            seen,
+            vec![
+                ("ERROR: Hello, world 2".to_string(), Line(1)),
+                ("warn: Hello, world 4".to_string(), Line(3)),
+            ]
+        );
+        assert_eq!(handle.filtered_lines(), 2);
+        assert_eq!((state.lines_read, state.seq), (4, 2));
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn run_no_follow_test() {
+        let temp_dir = TempDir::new("chase-test-sync-no-follow").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.follow = false;
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+
+        // Returns by itself once it gets to the end of the file
+        let mut seen = Vec::new();
+        let state = chaser
+            .run_with_handle(&ControlHandle::new(), |line, _, _| {
+                seen.push(line.to_string());
+                Ok(Control::Continue)
+            })
+            .unwrap();
+        assert_eq!(seen, vec!["Hello, world 1", "Hello, world 2"]);
+        assert_eq!((state.line, state.lines_read), (Line(2), 2));
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
 }
@@ -0,0 +1,579 @@
+//! Holds logic for finding and parsing the timestamps of lines.
+
+use data::*;
+use errors::ChaseError;
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDateTime, TimeZone, Utc};
+use chrono::format::{Item, StrftimeItems};
+use regex::Regex;
+
+#[cfg(feature = "with-serde")]
+use serde_json::{self, Value};
+
+use std::fmt;
+use std::io::{self, BufRead, Seek, SeekFrom};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const RFC3339_PATTERN: &str =
+    r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|z|[+-]\d{2}:?\d{2})?";
+const SYSLOG_PATTERN: &str =
+    r"\b(?:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) +\d{1,2} \d{2}:\d{2}:\d{2}\b";
+const EPOCH_SECONDS_PATTERN: &str = r"\b\d{9,11}(?:\.\d+)?\b";
+const EPOCH_MILLIS_PATTERN: &str = r"\b\d{12,14}\b";
+
+/// A way of finding and parsing the timestamp of a line. Timestamps that don't say what
+/// their UTC offset is are taken to be in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum TimestampFormat {
+    /// The first RFC3339 timestamp in the line, e.g. `2018-02-03T04:05:06.789Z`. A space
+    /// is accepted in place of the `T`, as is a missing offset. This also covers the
+    /// timestamps of RFC5424 syslog lines.
+    Rfc3339,
+    /// The first BSD (RFC3164) syslog timestamp in the line, e.g. `Feb  3 04:05:06`. These
+    /// don't have a year, so the most recent one that isn't more than a day in the future
+    /// is assumed.
+    Syslog,
+    /// The first number in the line with 9 to 11 digits (and maybe a fraction), as seconds
+    /// since the epoch
+    EpochSeconds,
+    /// The first number in the line with 12 to 14 digits, as milliseconds since the epoch
+    EpochMillis,
+    /// A strftime pattern, as understood by chrono (e.g. `%d/%b/%Y:%H:%M:%S %z`), that the
+    /// line starts with, after any whitespace or `[`
+    Strftime(String),
+    /// The value at a dot-separated path in a JSON line (e.g. `meta.time`, with numbers
+    /// used as indices into arrays). Strings are parsed as RFC3339 and numbers as epoch
+    /// seconds, or milliseconds if they are too big to be seconds.
+    #[cfg(feature = "with-serde")]
+    JsonField(String),
+}
+
+/// What `Chaser::since` goes by when the chaser has no TimestampFormats of its own
+const SINCE_FORMATS: &[TimestampFormat] = &[TimestampFormat::Rfc3339, TimestampFormat::Syslog];
+
+/// Finds the timestamps of lines, trying each of a Chaser's TimestampFormats in turn
+#[derive(Debug)]
+pub(crate) struct Extractor {
+    formats: Vec<TimestampFormat>,
+    rfc3339: Regex,
+    syslog: Regex,
+    epoch_seconds: Regex,
+    epoch_millis: Regex,
+}
+
+impl Extractor {
+    /// Gives back None if there are no formats to try
+    pub(crate) fn compile(formats: &[TimestampFormat]) -> Result<Option<Extractor>, ChaseError> {
+        if formats.is_empty() {
+            return Ok(None);
+        }
+        for format in formats {
+            if let TimestampFormat::Strftime(ref pattern) = *format {
+                if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+                    return Err(ChaseError::InvalidPattern {
+                        pattern: pattern.clone(),
+                        source: Box::new(InvalidStrftime),
+                    });
+                }
+            }
+        }
+        Ok(Some(Extractor {
+            formats: formats.to_vec(),
+            rfc3339: known_regex(RFC3339_PATTERN),
+            syslog: known_regex(SYSLOG_PATTERN),
+            epoch_seconds: known_regex(EPOCH_SECONDS_PATTERN),
+            epoch_millis: known_regex(EPOCH_MILLIS_PATTERN),
+        }))
+    }
+
+    /// The timestamp of the line, according to the first format that finds one
+    pub(crate) fn extract(&self, text: &str) -> Option<SystemTime> {
+        self.formats
+            .iter()
+            .filter_map(|format| self.extract_with(format, text))
+            .next()
+    }
+
+    fn extract_with(&self, format: &TimestampFormat, text: &str) -> Option<SystemTime> {
+        match *format {
+            TimestampFormat::Rfc3339 => self.rfc3339
+                .find(text)
+                .and_then(|found| parse_rfc3339(found.as_str())),
+            TimestampFormat::Syslog => self.syslog
+                .find(text)
+                // Syslog leaves out the year, so only then is the current one needed
+                .and_then(|found| parse_syslog(found.as_str(), Utc::now())),
+            TimestampFormat::EpochSeconds => self.epoch_seconds
+                .find(text)
+                .and_then(|found| from_epoch_seconds(found.as_str())),
+            TimestampFormat::EpochMillis => self.epoch_millis
+                .find(text)
+                .and_then(|found| found.as_str().parse().ok())
+                .map(from_epoch_millis),
+            TimestampFormat::Strftime(ref pattern) => parse_strftime(text, pattern),
+            #[cfg(feature = "with-serde")]
+            TimestampFormat::JsonField(ref path) => {
+                let value: Value = serde_json::from_str(text).ok()?;
+                let field = path.split('.').try_fold(&value, |value, key| match *value {
+                    Value::Array(ref items) => key.parse().ok().and_then(|i: usize| items.get(i)),
+                    _ => value.get(key),
+                })?;
+                match *field {
+                    Value::String(ref s) => self.rfc3339
+                        .find(s)
+                        .and_then(|found| parse_rfc3339(found.as_str())),
+                    Value::Number(ref n) => match n.as_u64() {
+                        Some(millis) if millis >= 100_000_000_000 => {
+                            Some(from_epoch_millis(millis))
+                        }
+                        _ => from_epoch_seconds(&n.to_string()),
+                    },
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Finds the first line with a timestamp at or after `since`, so that chasing can start
+/// there, using the chaser's TimestampFormats (or RFC3339 and syslog ones if it has none).
+///
+/// This binary searches the file by byte offset, moving on to the start of the next line
+/// at each probe, and assumes that timestamps only ever go up. Lines without a timestamp
+/// are passed over. Line numbers are then counted up to the line that was found. If there
+/// is no such line, we start from the end of the file.
+pub(crate) fn find_since<R>(
+    reader: &mut R,
+    formats: &[TimestampFormat],
+    since: SystemTime,
+) -> Result<(Line, Pos), ChaseError>
+where
+    R: BufRead + Seek,
+{
+    let formats = if formats.is_empty() {
+        SINCE_FORMATS
+    } else {
+        formats
+    };
+    let extractor = match Extractor::compile(formats)? {
+        Some(extractor) => extractor,
+        None => return Ok((Line(0), Pos(0))),
+    };
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut low = 0;
+    let mut high = len;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match first_timestamp_from(reader, &extractor, mid)? {
+            // Everything up to and including that line is too early
+            Some((start, timestamp)) if timestamp < since => low = start + 1,
+            _ => high = mid,
+        }
+    }
+    let start = match first_timestamp_from(reader, &extractor, low)? {
+        Some((start, _)) => start,
+        None => line_start_from(reader, len)?,
+    };
+    Ok((count_lines(reader, start)?, Pos(start)))
+}
+
+// The start and timestamp of the first line with one that starts at or after `offset`
+fn first_timestamp_from<R>(
+    reader: &mut R,
+    extractor: &Extractor,
+    offset: u64,
+) -> io::Result<Option<(u64, SystemTime)>>
+where
+    R: BufRead + Seek,
+{
+    let mut start = line_start_from(reader, offset)?;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read_bytes = reader.read_until(b'\n', &mut line)? as u64;
+        if read_bytes == 0 {
+            return Ok(None);
+        }
+        let text = String::from_utf8_lossy(&line);
+        if let Some(timestamp) = extractor.extract(text.trim_end_matches('\n')) {
+            return Ok(Some((start, timestamp)));
+        }
+        start += read_bytes;
+    }
+}
+
+// Moves the reader to the start of the first line that starts at or after `offset`,
+// giving back where that is
+fn line_start_from<R>(reader: &mut R, offset: u64) -> io::Result<u64>
+where
+    R: BufRead + Seek,
+{
+    if offset == 0 {
+        return reader.seek(SeekFrom::Start(0));
+    }
+    // Starting a byte early means that if `offset` is the start of a line, that is the
+    // line we end up at
+    reader.seek(SeekFrom::Start(offset - 1))?;
+    let mut skipped = Vec::new();
+    let read_bytes = reader.read_until(b'\n', &mut skipped)? as u64;
+    Ok(offset - 1 + read_bytes)
+}
+
+// Counts the lines before `pos`, leaving the reader there
+fn count_lines<R>(reader: &mut R, pos: u64) -> io::Result<Line>
+where
+    R: BufRead + Seek,
+{
+    reader.seek(SeekFrom::Start(0))?;
+    let mut lines = 0;
+    let mut remaining = pos;
+    while remaining > 0 {
+        let consumed = {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            let available = buffer.len().min(remaining as usize);
+            lines += buffer[..available].iter().filter(|&&b| b == b'\n').count();
+            available
+        };
+        reader.consume(consumed);
+        remaining -= consumed as u64;
+    }
+    Ok(Line(lines))
+}
+
+impl<'a> Chasing<'a> {
+    /// The timestamp of the line that was just read, if the chaser has TimestampFormats.
+    /// Lines none of them find a timestamp in are counted on the ControlHandle.
+    pub(crate) fn timestamp(&self) -> Option<SystemTime> {
+        let extractor = self.timestamps.as_ref()?;
+        let found = extractor.extract(self.text());
+        if found.is_none() {
+            self.handle.count_untimestamped_lines(1);
+        }
+        found
+    }
+}
+
+/// A strftime pattern that chrono doesn't understand
+#[derive(Debug)]
+struct InvalidStrftime;
+
+impl fmt::Display for InvalidStrftime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "not a valid strftime pattern")
+    }
+}
+
+impl ::std::error::Error for InvalidStrftime {}
+
+fn known_regex(pattern: &str) -> Regex {
+    Regex::new(pattern).expect("Built-in timestamp patterns are valid")
+}
+
+fn parse_rfc3339(found: &str) -> Option<SystemTime> {
+    let normalised = found.replacen(' ', "T", 1).replacen(',', ".", 1);
+    if normalised.ends_with('Z') || normalised.ends_with('z') {
+        let without_zone = &normalised[..normalised.len() - 1];
+        NaiveDateTime::parse_from_str(without_zone, "%Y-%m-%dT%H:%M:%S%.f")
+            .ok()
+            .map(from_utc)
+    } else {
+        DateTime::parse_from_str(&normalised, "%Y-%m-%dT%H:%M:%S%.f%z")
+            .map(SystemTime::from)
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(&normalised, "%Y-%m-%dT%H:%M:%S%.f").map(from_utc)
+            })
+            .ok()
+    }
+}
+
+fn parse_syslog(found: &str, now: DateTime<Utc>) -> Option<SystemTime> {
+    let parse_in = |year: i32| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", year, found), "%Y %b %e %H:%M:%S").ok()
+    };
+    let this_year = parse_in(now.year());
+    let parsed = match this_year {
+        Some(parsed) if parsed <= now.naive_utc() + ChronoDuration::days(1) => Some(parsed),
+        // Either it's from last year, or it's the 29th of February and this isn't a leap
+        // year
+        _ => parse_in(now.year() - 1),
+    };
+    parsed.map(from_utc)
+}
+
+fn parse_strftime(text: &str, pattern: &str) -> Option<SystemTime> {
+    let start = text.trim_start_matches(|c: char| c.is_whitespace() || c == '[');
+    DateTime::parse_and_remainder(start, pattern)
+        .map(|(parsed, _)| SystemTime::from(parsed))
+        .or_else(|_| {
+            NaiveDateTime::parse_and_remainder(start, pattern).map(|(parsed, _)| from_utc(parsed))
+        })
+        .ok()
+}
+
+fn from_utc(naive: NaiveDateTime) -> SystemTime {
+    SystemTime::from(Utc.from_utc_datetime(&naive))
+}
+
+// Parses the fraction digit by digit, rather than as a float, so that no precision is lost
+fn from_epoch_seconds(seconds: &str) -> Option<SystemTime> {
+    let mut parts = seconds.splitn(2, '.');
+    let whole = parts.next()?.parse().ok()?;
+    let fraction = parts.next().unwrap_or("");
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = fraction
+        .chars()
+        .chain(::std::iter::repeat('0'))
+        .take(9)
+        .collect::<String>()
+        .parse()
+        .ok()?;
+    Some(UNIX_EPOCH + Duration::new(whole, nanos))
+}
+
+fn from_epoch_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use control::*;
+    use errors::ChaseError;
+    use sync::EachRecord;
+    use timestamp::*;
+    use tempdir::*;
+    use std::io::{Cursor, Write};
+
+    use std::fs::OpenOptions;
+
+    fn at(rfc3339: &str) -> SystemTime {
+        SystemTime::from(DateTime::parse_from_rfc3339(rfc3339).unwrap())
+    }
+
+    fn extract(format: TimestampFormat, text: &str) -> Option<SystemTime> {
+        Extractor::compile(&[format]).unwrap().unwrap().extract(text)
+    }
+
+    #[test]
+    fn extract_test() {
+        let expected = Some(at("2018-02-03T04:05:06.789Z"));
+        for text in &[
+            "2018-02-03T04:05:06.789Z INFO hello",
+            "INFO [2018-02-03 04:05:06,789] hello",
+            "<34>1 2018-02-03T05:05:06.789+01:00 host app - - hello",
+            "2018-02-03T00:05:06.789-0400 hello",
+        ] {
+            assert_eq!(extract(TimestampFormat::Rfc3339, text), expected, "{}", text);
+        }
+        assert_eq!(extract(TimestampFormat::Rfc3339, "Feb  3 04:05:06 hello"), None);
+
+        assert_eq!(
+            extract(TimestampFormat::EpochSeconds, "ts=1517630706.789 hello"),
+            expected
+        );
+        assert_eq!(
+            extract(TimestampFormat::EpochMillis, "1517630706789 hello"),
+            expected
+        );
+        assert_eq!(extract(TimestampFormat::EpochMillis, "1517630706 hello"), None);
+
+        let apache = TimestampFormat::Strftime("%d/%b/%Y:%H:%M:%S %z".to_string());
+        assert_eq!(
+            extract(apache, "[03/Feb/2018:05:05:06 +0100] \"GET / HTTP/1.1\" 200"),
+            Some(at("2018-02-03T04:05:06Z"))
+        );
+        let naive = TimestampFormat::Strftime("%Y%m%d %H%M%S".to_string());
+        assert_eq!(
+            extract(naive, "20180203 040506 hello"),
+            Some(at("2018-02-03T04:05:06Z"))
+        );
+        let formats = vec![
+            TimestampFormat::Strftime("%H:%M".to_string()),
+            TimestampFormat::Rfc3339,
+        ];
+        let extractor = Extractor::compile(&formats).unwrap().unwrap();
+        assert_eq!(extractor.extract("2018-02-03T04:05:06.789Z"), expected);
+        assert_eq!(extractor.extract("hello"), None);
+    }
+
+    #[test]
+    fn syslog_test() {
+        let now = DateTime::parse_from_rfc3339("2018-02-03T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_syslog("Feb  3 04:05:06", now),
+            Some(at("2018-02-03T04:05:06Z"))
+        );
+        assert_eq!(
+            parse_syslog("Feb 4 04:05:06", now),
+            Some(at("2018-02-04T04:05:06Z"))
+        );
+        assert_eq!(
+            parse_syslog("Dec 31 23:59:59", now),
+            Some(at("2017-12-31T23:59:59Z"))
+        );
+        assert!(extract(TimestampFormat::Syslog, "<34>Feb  3 04:05:06 host app: hello").is_some());
+    }
+
+    #[test]
+    fn invalid_strftime_test() {
+        let formats = vec![TimestampFormat::Strftime("%Y-%Q".to_string())];
+        match Extractor::compile(&formats) {
+            Err(ChaseError::InvalidPattern { pattern, .. }) => assert_eq!(pattern, "%Y-%Q"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "with-serde")]
+    #[test]
+    fn json_field_test() {
+        let format = TimestampFormat::JsonField("meta.times.1".to_string());
+        assert_eq!(
+            extract(
+                format.clone(),
+                r#"{"meta": {"times": [0, "2018-02-03T04:05:06Z"]}}"#
+            ),
+            Some(at("2018-02-03T04:05:06Z"))
+        );
+        assert_eq!(
+            extract(format.clone(), r#"{"meta": {"times": [0, 1517630706789]}}"#),
+            Some(at("2018-02-03T04:05:06.789Z"))
+        );
+        assert_eq!(extract(format.clone(), r#"{"meta": {"times": [0]}}"#), None);
+        assert_eq!(extract(format, "not json"), None);
+    }
+
+    #[test]
+    fn find_since_test() {
+        let mut contents = String::new();
+        let mut starts = Vec::new();
+        for n in 0..50 {
+            starts.push(contents.len() as u64);
+            contents.push_str(&format!("2018-02-03T04:{:02}:00Z line {}\n", n, n));
+            if n % 7 == 0 {
+                // Lines without timestamps, such as stack traces, get passed over
+                contents.push_str("    at somewhere\n    at somewhere else\n");
+            }
+        }
+        let line_of = |n: usize| n + 2 * n.div_ceil(7);
+        let mut reader = Cursor::new(contents.clone().into_bytes());
+        for n in 0..50 {
+            let exactly = format!("2018-02-03T04:{:02}:00Z", n);
+            assert_eq!(
+                find_since(&mut reader, &[], at(&exactly)).unwrap(),
+                (Line(line_of(n)), Pos(starts[n])),
+                "{}",
+                exactly
+            );
+            if n < 49 {
+                let just_after = format!("2018-02-03T04:{:02}:00.5Z", n);
+                assert_eq!(
+                    find_since(&mut reader, &[], at(&just_after)).unwrap(),
+                    (Line(line_of(n + 1)), Pos(starts[n + 1])),
+                    "{}",
+                    just_after
+                );
+            }
+        }
+        assert_eq!(
+            find_since(&mut reader, &[], at("2000-01-01T00:00:00Z")).unwrap(),
+            (Line(0), Pos(0))
+        );
+        assert_eq!(
+            find_since(&mut reader, &[], at("2020-01-01T00:00:00Z")).unwrap(),
+            (Line(line_of(50)), Pos(contents.len() as u64))
+        );
+        let mut empty = Cursor::new(Vec::new());
+        assert_eq!(
+            find_since(&mut empty, &[], at("2020-01-01T00:00:00Z")).unwrap(),
+            (Line(0), Pos(0))
+        );
+    }
+
+    #[test]
+    fn run_since_test() {
+        let temp_dir = TempDir::new("chase-test-timestamp-since").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.since = Some(at("2018-02-03T04:05:30Z"));
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "2018-02-03T04:05:06Z Hello, world 1").unwrap();
+        writeln!(file_write, "2018-02-03T04:05:07Z Hello, world 2").unwrap();
+        writeln!(file_write, "2018-02-03T04:06:00Z Hello, world 3").unwrap();
+
+        let mut seen = Vec::new();
+        chaser
+            .run(|line, num, pos| {
+                seen.push((line.to_string(), num, pos));
+                Ok(Control::Stop)
+            })
+            .unwrap();
+        assert_eq!(
+            seen,
+            vec![
+                (
+                    "2018-02-03T04:06:00Z Hello, world 3".to_string(),
+                    Line(2),
+                    Pos(72),
+                ),
+            ]
+        );
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn run_records_timestamp_test() {
+        let temp_dir = TempDir::new("chase-test-timestamp").unwrap();
+        let file_path = temp_dir.path().join("test.log");
+        let mut chaser = Chaser::new(&file_path);
+        chaser.timestamps = vec![TimestampFormat::Rfc3339, TimestampFormat::EpochSeconds];
+
+        let mut file_write = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&file_path)
+            .unwrap();
+        writeln!(file_write, "2018-02-03T04:05:06Z Hello, world 1").unwrap();
+        writeln!(file_write, "Hello, world 2").unwrap();
+        writeln!(file_write, "1517630707 Hello, world 3").unwrap();
+
+        let handle = ControlHandle::new();
+        let mut seen = Vec::new();
+        chaser
+            .run_controlled(
+                &handle,
+                EachRecord(|record: &Record| {
+                    seen.push((record.line, record.timestamp));
+                    Ok(if seen.len() < 3 {
+                        Control::Continue
+                    } else {
+                        Control::Stop
+                    })
+                }),
+            )
+            .unwrap();
+        assert_eq!(
+            seen,
+            vec![
+                (Line(0), Some(at("2018-02-03T04:05:06Z"))),
+                (Line(1), None),
+                (Line(2), Some(at("2018-02-03T04:05:07Z"))),
+            ]
+        );
+        assert_eq!(handle.untimestamped_lines(), 1);
+        drop(file_write);
+        temp_dir.close().unwrap();
+    }
+}